@@ -1,194 +1,450 @@
 use core::fmt;
-use std::ops::Div;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
+};
+
+// a run of `len` blocks belonging to file `id`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Extent {
+    id: u32,
+    start: usize,
+    len: usize,
+}
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-enum BlockType {
-    File,
-    FreeSpace,
+// a run of free blocks
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Span {
+    start: usize,
+    len: usize,
 }
 
+// the disk is only described by where the files are, everything else is free
 #[derive(Debug, Clone)]
-struct Block {
-    block_type: BlockType,
-    id: Option<u32>,
+struct Disk {
+    files: Vec<Extent>,
+    size: usize,
 }
 
-struct File {
-    blocks: Vec<Block>,
+impl Disk {
+    // the free runs between the files, in order
+    fn free_spans(&self) -> Vec<Span> {
+        let mut files: Vec<&Extent> = self.files.iter().filter(|file| file.len > 0).collect();
+        files.sort_by_key(|file| file.start);
+
+        let mut spans = Vec::new();
+        let mut position = 0;
+        for file in files {
+            if file.start > position {
+                spans.push(Span {
+                    start: position,
+                    len: file.start - position,
+                });
+            }
+            position = file.start + file.len;
+        }
+        if self.size > position {
+            spans.push(Span {
+                start: position,
+                len: self.size - position,
+            });
+        }
+        spans
+    }
 }
 
-impl fmt::Display for File {
+impl fmt::Display for Disk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for block in &self.blocks {
-            match block.block_type {
-                BlockType::File => {
-                    write!(f, "{}", block.id.expect("file has no id"))?;
-                }
-                BlockType::FreeSpace => {
-                    write!(f, ".")?;
-                }
+        let mut blocks = vec![None; self.size];
+        for file in &self.files {
+            blocks[file.start..file.start + file.len].fill(Some(file.id));
+        }
+        for block in blocks {
+            match block {
+                Some(id) => write!(f, "{}", id)?,
+                None => write!(f, ".")?,
             }
         }
         Ok(())
     }
 }
+
 fn get_input(file_path: &str) -> String {
     std::fs::read_to_string(file_path).unwrap()
 }
 
-fn parse_input(input: &str) -> File {
-    let blocks = input
-        .trim()
-        .chars()
-        .enumerate()
-        .flat_map(|(i, c)| {
-            let size = c.to_digit(10).unwrap() as usize;
+fn parse_input(input: &str) -> Disk {
+    let mut files = Vec::new();
+    let mut position = 0;
+    for (i, c) in input.trim().chars().enumerate() {
+        let len = c.to_digit(10).unwrap() as usize;
+        if i % 2 == 0 {
+            files.push(Extent {
+                id: (i / 2) as u32,
+                start: position,
+                len,
+            });
+        }
+        position += len;
+    }
 
-            let block_type = if i % 2 == 0 {
-                BlockType::File
-            } else {
-                BlockType::FreeSpace
-            };
+    Disk {
+        files,
+        size: position,
+    }
+}
 
-            let id = if block_type == BlockType::File {
-                Some(i.div(2) as u32)
-            } else {
-                None
-            };
+// moves single blocks from the end of the disk into the leftmost free blocks,
+// splitting files into several extents
+fn move_blocks(disk: &mut Disk) {
+    let mut spans = disk.free_spans();
+    let mut next_span = 0;
+    let mut moved = Vec::with_capacity(disk.files.len());
+
+    for file in disk.files.iter().rev() {
+        let mut remaining = file.len;
+        while remaining > 0 && next_span < spans.len() && spans[next_span].start < file.start {
+            let span = &mut spans[next_span];
+            let len = remaining.min(span.len);
+            moved.push(Extent {
+                id: file.id,
+                start: span.start,
+                len,
+            });
+            span.start += len;
+            span.len -= len;
+            if span.len == 0 {
+                next_span += 1;
+            }
+            remaining -= len;
+        }
+        if remaining > 0 {
+            moved.push(Extent {
+                len: remaining,
+                ..*file
+            });
+        }
+    }
 
-            vec![Block { block_type, id }; size]
-        })
-        .collect();
+    moved.sort_by_key(|extent| extent.start);
+    disk.files = moved;
+}
+
+// hands out free space for whole files
+trait Allocator {
+    fn name(&self) -> &'static str;
 
-    File { blocks }
+    // forgets all earlier free space and starts over with `spans`
+    fn reset(&mut self, spans: &[Span]);
+
+    // takes `len` blocks from a free span that starts before `limit` and
+    // returns where they start
+    fn allocate(&mut self, len: usize, limit: usize) -> Option<usize>;
 }
 
-fn move_blocks(blocks: &mut [Block]) {
-    let mut first_empty_block = 0;
-    let mut last_file_block = blocks.len() - 1;
-    loop {
-        for i in first_empty_block..blocks.len() {
-            if blocks[i].block_type == BlockType::FreeSpace {
-                first_empty_block = i;
-                break;
-            }
-        }
+// free spans kept in one min-heap of start positions per length, only lengths
+// that occur get a heap, as merged spans and the end of the disk can be long
+#[derive(Debug, Default)]
+struct SizeClasses {
+    free: BTreeMap<usize, BinaryHeap<Reverse<usize>>>,
+}
 
-        for i in (0..=last_file_block).rev() {
-            if blocks[i].block_type == BlockType::File {
-                last_file_block = i;
-                break;
-            }
+impl SizeClasses {
+    fn reset(&mut self, spans: &[Span]) {
+        self.free.clear();
+        for span in spans {
+            self.push(span.len, span.start);
         }
+    }
 
-        if first_empty_block >= last_file_block {
-            break;
-        }
+    fn push(&mut self, len: usize, start: usize) {
+        self.free.entry(len).or_default().push(Reverse(start));
+    }
 
-        blocks.swap(last_file_block, first_empty_block);
+    // the leftmost span of every length that fits, as (length, start)
+    fn candidates(&self, len: usize, limit: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.free
+            .range(len..)
+            .filter_map(move |(&span_len, heap)| match heap.peek() {
+                Some(&Reverse(start)) if start < limit => Some((span_len, start)),
+                _ => None,
+            })
     }
-}
 
-fn find_last_block(blocks: &[Block]) -> Option<(usize, usize)> {
-    for i in (0..blocks.len()).rev() {
-        if blocks[i].block_type == BlockType::FreeSpace {
-            continue;
+    fn take(&mut self, span_len: usize, len: usize) -> usize {
+        let heap = self.free.get_mut(&span_len).unwrap();
+        let Reverse(start) = heap.pop().unwrap();
+        if heap.is_empty() {
+            self.free.remove(&span_len);
         }
-
-        let mut j = i;
-        let id = blocks[i].id.expect("file has no id");
-        loop {
-            if blocks[j].block_type != BlockType::File || blocks[j].id != Some(id) || j == 0 {
-                return Some((j + 1, i - j));
-            }
-            j -= 1
+        if span_len > len {
+            self.push(span_len - len, start + len);
         }
+        start
+    }
+}
+
+// the leftmost span that fits
+#[derive(Debug, Default)]
+struct FirstFit(SizeClasses);
+
+impl Allocator for FirstFit {
+    fn name(&self) -> &'static str {
+        "first-fit"
+    }
+
+    fn reset(&mut self, spans: &[Span]) {
+        self.0.reset(spans);
     }
 
-    None
+    fn allocate(&mut self, len: usize, limit: usize) -> Option<usize> {
+        let (span_len, _) = self
+            .0
+            .candidates(len, limit)
+            .min_by_key(|&(_, start)| start)?;
+        Some(self.0.take(span_len, len))
+    }
 }
 
-fn find_first_empty_block_of_size(size: usize, blocks: &[Block]) -> Option<usize> {
-    for i in 0..blocks.len() - size + 1 {
-        if blocks[i..i + size]
-            .iter()
-            .all(|b| b.block_type == BlockType::FreeSpace)
-        {
-            return Some(i);
-        }
+// the shortest span that fits, leftmost among equals
+#[derive(Debug, Default)]
+struct BestFit(SizeClasses);
+
+impl Allocator for BestFit {
+    fn name(&self) -> &'static str {
+        "best-fit"
     }
 
-    None
+    fn reset(&mut self, spans: &[Span]) {
+        self.0.reset(spans);
+    }
+
+    fn allocate(&mut self, len: usize, limit: usize) -> Option<usize> {
+        let (span_len, _) = self.0.candidates(len, limit).next()?;
+        Some(self.0.take(span_len, len))
+    }
 }
 
-fn swap_full_blocks(i: usize, j: usize, size: usize, blocks: &mut [Block]) {
-    for k in 0..size {
-        blocks.swap(i + k, j + k);
+// the longest span that fits, leftmost among equals
+#[derive(Debug, Default)]
+struct WorstFit(SizeClasses);
+
+impl Allocator for WorstFit {
+    fn name(&self) -> &'static str {
+        "worst-fit"
+    }
+
+    fn reset(&mut self, spans: &[Span]) {
+        self.0.reset(spans);
+    }
+
+    fn allocate(&mut self, len: usize, limit: usize) -> Option<usize> {
+        let (span_len, _) = self.0.candidates(len, limit).last()?;
+        Some(self.0.take(span_len, len))
     }
 }
 
-fn move_full_blocks(blocks: &mut [Block]) {
-    let len = blocks.len();
-    let mut slice = &mut blocks[0..len];
-    loop {
-        if slice.len() == 1 {
-            break;
-        }
+// the first span that fits after the previous allocation, wrapping around to
+// the start of the disk, the spans are kept ordered by start per length so
+// the search does not have to walk past every span that is too short
+#[derive(Debug, Default)]
+struct NextFit {
+    free: BTreeMap<usize, BTreeSet<usize>>,
+    cursor: usize,
+}
 
-        let last_full_block_index = find_last_block(slice);
+impl Allocator for NextFit {
+    fn name(&self) -> &'static str {
+        "next-fit"
+    }
 
-        let Some((last_full_block_index, last_full_block_size)) = last_full_block_index else {
-            break;
-        };
+    fn reset(&mut self, spans: &[Span]) {
+        self.free.clear();
+        for span in spans {
+            self.free.entry(span.len).or_default().insert(span.start);
+        }
+        self.cursor = 0;
+    }
 
-        let first_empty_block_index = find_first_empty_block_of_size(last_full_block_size, slice);
-        let Some(first_empty_block_index) = first_empty_block_index else {
-            slice = &mut slice[0..last_full_block_index];
-            continue;
+    fn allocate(&mut self, len: usize, limit: usize) -> Option<usize> {
+        let cursor = self.cursor.min(limit);
+        let first_in = |from: usize, to: usize| {
+            self.free
+                .range(len..)
+                .filter_map(|(&span_len, starts)| {
+                    starts
+                        .range(from..to)
+                        .next()
+                        .map(|&start| (start, span_len))
+                })
+                .min()
         };
+        let (start, span_len) = first_in(cursor, limit).or_else(|| first_in(0, cursor))?;
+
+        let starts = self.free.get_mut(&span_len).unwrap();
+        starts.remove(&start);
+        if starts.is_empty() {
+            self.free.remove(&span_len);
+        }
+        if span_len > len {
+            self.free
+                .entry(span_len - len)
+                .or_default()
+                .insert(start + len);
+        }
+        self.cursor = start + len;
+        Some(start)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Mode {
+    Once,
+    // passes are repeated until no file moves, so space freed by a later
+    // file can be used by another one
+    UntilStable,
+}
+
+// moves whole files, highest id first, to where `allocator` puts them, as long
+// as that is further left, returns how many passes were made and files moved
+fn compact(disk: &mut Disk, allocator: &mut dyn Allocator, mode: Mode) -> (usize, usize) {
+    let mut order: Vec<usize> = (0..disk.files.len()).collect();
+    order.sort_by_key(|&i| Reverse(disk.files[i].id));
+
+    let mut passes = 0;
+    let mut total_moved = 0;
+    loop {
+        passes += 1;
+        // the space a file leaves behind is held back until the next pass.
+        // in the first pass it is right of every file still to be moved anyway,
+        // in later passes files are no longer in order, so this keeps each pass
+        // working on the free spans it started with
+        allocator.reset(&disk.free_spans());
+        let mut moved = 0;
+        for &i in &order {
+            let file = &mut disk.files[i];
+            if file.len == 0 {
+                continue;
+            }
+            if let Some(start) = allocator.allocate(file.len, file.start) {
+                file.start = start;
+                moved += 1;
+            }
+        }
+        total_moved += moved;
+
+        if mode == Mode::Once || moved == 0 {
+            return (passes, total_moved);
+        }
+    }
+}
 
-        swap_full_blocks(
-            first_empty_block_index,
-            last_full_block_index,
-            last_full_block_size,
-            slice,
-        );
+// free space left between the files
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Fragmentation {
+    holes: usize,
+    hole_blocks: usize,
+    largest_hole: usize,
+    // the share of blocks up to the end of the last file that are free
+    ratio: f64,
+}
 
-        slice = &mut slice[0..last_full_block_index];
+fn measure_fragmentation(disk: &Disk) -> Fragmentation {
+    let end = disk
+        .files
+        .iter()
+        .map(|file| file.start + file.len)
+        .max()
+        .unwrap_or(0);
+    let holes: Vec<Span> = disk
+        .free_spans()
+        .into_iter()
+        .filter(|span| span.start < end)
+        .collect();
+    let hole_blocks = holes.iter().map(|span| span.len).sum::<usize>();
+
+    Fragmentation {
+        holes: holes.len(),
+        hole_blocks,
+        largest_hole: holes.iter().map(|span| span.len).max().unwrap_or(0),
+        ratio: if end > 0 {
+            hole_blocks as f64 / end as f64
+        } else {
+            0.0
+        },
     }
 }
 
-fn calc_checksum(blocks: &[Block]) -> u64 {
-    blocks
+fn calc_checksum(files: &[Extent]) -> u64 {
+    files
         .iter()
-        .enumerate()
-        .filter(|(_, b)| b.block_type == BlockType::File)
-        .map(|(i, b)| b.id.expect("file has no id") as u64 * i as u64)
+        .map(|file| {
+            let (start, len) = (file.start as u64, file.len as u64);
+            // the sum of the positions start..start + len
+            let positions = len * start + len * len.saturating_sub(1) / 2;
+            file.id as u64 * positions
+        })
         .sum::<u64>()
 }
 
 fn part01() {
     let input = get_input("input.txt");
-    let mut blocks = parse_input(&input);
+    let mut disk = parse_input(&input);
 
-    move_blocks(&mut blocks.blocks);
+    move_blocks(&mut disk);
 
-    let checksum = calc_checksum(&blocks.blocks);
+    let checksum = calc_checksum(&disk.files);
     println!("{}", checksum);
 }
 
 fn part02() {
     let input = get_input("input.txt");
-    let mut blocks = parse_input(&input);
+    let mut disk = parse_input(&input);
 
-    move_full_blocks(&mut blocks.blocks);
+    compact(&mut disk, &mut FirstFit::default(), Mode::Once);
 
-    let checksum = calc_checksum(&blocks.blocks);
+    let checksum = calc_checksum(&disk.files);
 
     println!("{}", checksum);
 }
 
+fn compare_strategies() {
+    let input = get_input("input.txt");
+    let disk = parse_input(&input);
+
+    let mut allocators: Vec<Box<dyn Allocator>> = vec![
+        Box::new(FirstFit::default()),
+        Box::new(BestFit::default()),
+        Box::new(WorstFit::default()),
+        Box::new(NextFit::default()),
+    ];
+    println!(
+        "{:<10} {:<12} {:>6} {:>6} {:>15} {:>6} {:>6} {:>8} {:>8}",
+        "strategy", "mode", "passes", "moved", "checksum", "holes", "blocks", "largest", "ratio"
+    );
+    for allocator in allocators.iter_mut() {
+        for mode in [Mode::Once, Mode::UntilStable] {
+            let mut compacted = disk.clone();
+            let (passes, moved) = compact(&mut compacted, allocator.as_mut(), mode);
+            let fragmentation = measure_fragmentation(&compacted);
+            println!(
+                "{:<10} {:<12} {:>6} {:>6} {:>15} {:>6} {:>6} {:>8} {:>8.4}",
+                allocator.name(),
+                format!("{:?}", mode),
+                passes,
+                moved,
+                calc_checksum(&compacted.files),
+                fragmentation.holes,
+                fragmentation.hole_blocks,
+                fragmentation.largest_hole,
+                fragmentation.ratio
+            );
+        }
+    }
+}
+
 fn main() {
     part01();
     part02();
+    compare_strategies();
 }
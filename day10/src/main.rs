@@ -1,7 +1,12 @@
-use std::{fmt, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Deref,
+};
 
+// `None` marks an impassable tile
 #[derive(Debug)]
-struct Map(Vec<Vec<i32>>);
+struct Map(Vec<Vec<Option<i32>>>);
 
 impl Map {
     fn width(&self) -> usize {
@@ -12,23 +17,29 @@ impl Map {
         self.len()
     }
 
-    fn within_bounds(&self, x: i32, y: i32) -> bool {
-        x >= 0 && x < self.width() as i32 && y >= 0 && y < self.height() as i32
+    fn within_bounds(&self, pos: IVec2D) -> bool {
+        pos.x >= 0 && pos.x < self.width() as i32 && pos.y >= 0 && pos.y < self.height() as i32
     }
 
-    fn score(&self, x: i32, y: i32) -> i32 {
-        self[x as usize][y as usize]
+    // rows are stored top to bottom, so the row index is `y`, blocked tiles
+    // read the same as tiles off the map
+    fn get(&self, pos: IVec2D) -> Option<i32> {
+        if self.within_bounds(pos) {
+            self[pos.y as usize][pos.x as usize]
+        } else {
+            None
+        }
     }
 }
 
 impl Deref for Map {
-    type Target = Vec<Vec<i32>>;
+    type Target = Vec<Vec<Option<i32>>>;
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 struct IVec2D {
     x: i32,
     y: i32,
@@ -43,14 +54,74 @@ impl From<(i32, i32)> for IVec2D {
 #[derive(Debug)]
 struct TrailHead {
     pos: IVec2D,
+    // number of summits reachable from here
     score: i32,
+    // number of distinct trails starting here
+    rating: i32,
+    summits: HashSet<IVec2D>,
+}
+
+// which steps a hiker may take and where trails start and end
+#[derive(Debug, Clone, Copy)]
+struct TrailRules {
+    start: i32,
+    summit: i32,
+    // how much higher the next tile may be, at least 1
+    max_ascent: i32,
+    // how much lower the next tile may be, 0 forbids going down
+    max_descent: i32,
+    diagonals: bool,
+}
+
+impl Default for TrailRules {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            summit: 9,
+            max_ascent: 1,
+            max_descent: 0,
+            diagonals: false,
+        }
+    }
+}
+
+impl TrailRules {
+    fn allows(&self, from: i32, to: i32) -> bool {
+        let diff = to - from;
+        (1..=self.max_ascent).contains(&diff) || (1..=self.max_descent).contains(&-diff)
+    }
+
+    // every step goes up, so no trail can visit a tile twice
+    fn ascending(&self) -> bool {
+        self.max_descent == 0
+    }
+}
+
+// what can be reached from a single cell by walking uphill
+#[derive(Debug, Clone, Default)]
+struct Trails {
+    summits: HashSet<IVec2D>,
+    paths: i32,
+}
+
+impl fmt::Display for TrailHead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}): score {}, rating {}",
+            self.pos.x, self.pos.y, self.score, self.rating
+        )
+    }
 }
 
 impl fmt::Display for Map {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for row in self.iter() {
             for col in row.iter() {
-                write!(f, "{}", col)?;
+                match col {
+                    Some(height) => write!(f, "{}", height)?,
+                    None => write!(f, ".")?,
+                }
             }
             writeln!(f)?;
         }
@@ -67,118 +138,674 @@ fn parse_input(input: String) -> Map {
         .lines()
         .map(|line| {
             line.chars()
-                .map(|c| c.to_digit(10).unwrap() as i32)
+                .map(|c| match c {
+                    '.' => None,
+                    _ => Some(c.to_digit(10).unwrap() as i32),
+                })
                 .collect()
         })
         .collect())
 }
 
-fn get_neighbors(x: i32, y: i32, map: &Map) -> Vec<IVec2D> {
-    let mut neighbors: Vec<IVec2D> = Vec::new();
-    if map.within_bounds(x - 1, y) {
-        neighbors.push((x - 1, y).into());
-    }
-    if map.within_bounds(x + 1, y) {
-        neighbors.push((x + 1, y).into());
-    }
-    if map.within_bounds(x, y - 1) {
-        neighbors.push((x, y - 1).into());
+// the four straight neighbours come first, then the diagonal ones
+const OFFSETS: [(i32, i32); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+fn get_neighbors(pos: IVec2D, map: &Map, diagonals: bool) -> Vec<IVec2D> {
+    let count = if diagonals { 8 } else { 4 };
+    OFFSETS[..count]
+        .iter()
+        .map(|(dx, dy)| IVec2D::from((pos.x + dx, pos.y + dy)))
+        .filter(|&neighbor| map.within_bounds(neighbor))
+        .collect()
+}
+
+// a cell's trails are the union of those of the neighbours it may step to,
+// which are all higher, so going from the top down every cell is visited once
+fn find_all_trails(map: &Map, rules: &TrailRules) -> Vec<Vec<Trails>> {
+    assert!(rules.ascending(), "trails that go down can loop");
+    let mut layers: Vec<Vec<IVec2D>> = vec![Vec::new(); 10];
+    for y in 0..map.height() as i32 {
+        for x in 0..map.width() as i32 {
+            let pos = IVec2D::from((x, y));
+            if let Some(height) = map.get(pos) {
+                layers[height as usize].push(pos);
+            }
+        }
     }
-    if map.within_bounds(x, y + 1) {
-        neighbors.push((x, y + 1).into());
+
+    let mut trails: Vec<Vec<Trails>> = vec![vec![Trails::default(); map.width()]; map.height()];
+    for height in (0..10).rev() {
+        for &pos in &layers[height] {
+            let mut cell = Trails::default();
+            if height as i32 == rules.summit {
+                cell.summits.insert(pos);
+                cell.paths = 1;
+            } else {
+                for neighbor in get_neighbors(pos, map, rules.diagonals) {
+                    match map.get(neighbor) {
+                        Some(next) if rules.allows(height as i32, next) => {}
+                        _ => continue,
+                    }
+                    let above = &trails[neighbor.y as usize][neighbor.x as usize];
+                    cell.summits.extend(&above.summits);
+                    cell.paths += above.paths;
+                }
+            }
+            trails[pos.y as usize][pos.x as usize] = cell;
+        }
     }
-    neighbors
+
+    trails
 }
 
-fn dfs(x: i32, y: i32, map: &Map, unique: bool) -> i32 {
-    let mut total_score = 0;
+// when trails may go down they are walked one by one instead, which takes
+// time proportional to the number of trails
+fn find_all_trailheads(map: &Map, rules: &TrailRules) -> Vec<TrailHead> {
+    let trails = rules.ascending().then(|| find_all_trails(map, rules));
+    let mut all_trail_heads: Vec<TrailHead> = Vec::new();
+    for y in 0..map.height() as i32 {
+        for x in 0..map.width() as i32 {
+            let pos = IVec2D::from((x, y));
+            if map.get(pos) != Some(rules.start) {
+                continue;
+            }
 
-    let mut stack: Vec<IVec2D> = Vec::new();
-    let mut visited: Vec<IVec2D> = Vec::new();
-    stack.push((x, y).into());
+            let trail_head = match &trails {
+                Some(trails) => {
+                    let cell = &trails[y as usize][x as usize];
+                    TrailHead {
+                        pos,
+                        score: cell.summits.len() as i32,
+                        rating: cell.paths,
+                        summits: cell.summits.clone(),
+                    }
+                }
+                None => {
+                    let trails = find_trails(map, rules, pos, None);
+                    let summits: HashSet<IVec2D> =
+                        trails.iter().map(|trail| *trail.last().unwrap()).collect();
+                    TrailHead {
+                        pos,
+                        score: summits.len() as i32,
+                        rating: trails.len() as i32,
+                        summits,
+                    }
+                }
+            };
+            all_trail_heads.push(trail_head);
+        }
+    }
 
-    loop {
-        let Some(pos) = stack.pop() else {
-            break;
-        };
-        if !unique && visited.contains(&pos) {
-            continue;
+    all_trail_heads
+}
+
+// every hiking trail from `start`, as the cells from the trailhead up to the
+// summit, in the order they are found, stopping after `limit` trails, a trail
+// never visits the same tile twice
+fn find_trails(
+    map: &Map,
+    rules: &TrailRules,
+    start: IVec2D,
+    limit: Option<usize>,
+) -> Vec<Vec<IVec2D>> {
+    fn walk(
+        map: &Map,
+        rules: &TrailRules,
+        path: &mut Vec<IVec2D>,
+        limit: usize,
+        trails: &mut Vec<Vec<IVec2D>>,
+    ) {
+        let pos = *path.last().unwrap();
+        let height = map.get(pos).unwrap();
+        if height == rules.summit {
+            trails.push(path.clone());
+            return;
+        }
+        for neighbor in get_neighbors(pos, map, rules.diagonals) {
+            if trails.len() >= limit {
+                return;
+            }
+            let Some(next) = map.get(neighbor) else {
+                continue;
+            };
+            if rules.allows(height, next) && !path.contains(&neighbor) {
+                path.push(neighbor);
+                walk(map, rules, path, limit, trails);
+                path.pop();
+            }
         }
-        visited.push(pos);
-        let score = map.score(pos.x, pos.y);
+    }
 
-        if score == 9 {
-            total_score += 1;
-            continue;
+    let mut trails = Vec::new();
+    if map.get(start) == Some(rules.start) {
+        walk(
+            map,
+            rules,
+            &mut vec![start],
+            limit.unwrap_or(usize::MAX),
+            &mut trails,
+        );
+    }
+    trails
+}
+
+fn trails_to_json(trails: &[Vec<IVec2D>]) -> String {
+    let trails = trails
+        .iter()
+        .map(|trail| {
+            let cells = trail
+                .iter()
+                .map(|pos| format!("{{\"x\":{},\"y\":{}}}", pos.x, pos.y))
+                .collect::<Vec<String>>()
+                .join(",");
+            format!("[{}]", cells)
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{{\"trails\":[{}]}}\n", trails)
+}
+
+// one copy of the map per trail, with every cell off the trail blanked out
+fn render_trails(map: &Map, trails: &[Vec<IVec2D>]) -> String {
+    let mut out = String::new();
+    for (i, trail) in trails.iter().enumerate() {
+        out.push_str(&format!("trail {}:\n", i + 1));
+        for y in 0..map.height() as i32 {
+            for x in 0..map.width() as i32 {
+                let pos = IVec2D::from((x, y));
+                if trail.contains(&pos) {
+                    out.push_str(&map.get(pos).unwrap().to_string());
+                } else {
+                    out.push('.');
+                }
+            }
+            out.push('\n');
         }
+    }
+    out
+}
 
-        let neighbors = get_neighbors(pos.x, pos.y, map);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Score,
+    Rating,
+}
 
-        for neighbor in neighbors {
-            let neighbor_score = map.score(neighbor.x, neighbor.y);
-            let diff = neighbor_score - score;
+// the values drawn on top of the heightmap, trailheads by the chosen metric
+// and summits by how many trailheads reach them
+struct Overlay {
+    trail_heads: HashMap<IVec2D, i32>,
+    summits: HashMap<IVec2D, i32>,
+}
 
-            if diff == 1 {
-                stack.push(neighbor);
+impl Overlay {
+    fn new(trail_heads: &[TrailHead], metric: Metric) -> Self {
+        let mut summits: HashMap<IVec2D, i32> = HashMap::new();
+        for th in trail_heads {
+            for &summit in &th.summits {
+                *summits.entry(summit).or_insert(0) += 1;
             }
         }
+        let trail_heads = trail_heads
+            .iter()
+            .map(|th| match metric {
+                Metric::Score => (th.pos, th.score),
+                Metric::Rating => (th.pos, th.rating),
+            })
+            .collect();
+        Self {
+            trail_heads,
+            summits,
+        }
     }
 
-    total_score
+    fn max_trail_head(&self) -> i32 {
+        self.trail_heads.values().copied().max().unwrap_or(0)
+    }
+
+    fn max_summit(&self) -> i32 {
+        self.summits.values().copied().max().unwrap_or(0)
+    }
 }
 
-fn find_all_trailhead_scores(map: &Map, unique: bool) -> Vec<TrailHead> {
-    let mut all_trail_heads: Vec<TrailHead> = Vec::new();
-    for y in 0..map.len() {
-        for x in 0..map[y].len() {
-            if map.score(x as i32, y as i32) != 0 {
+type Rgb = (u8, u8, u8);
+
+fn lerp(from: Rgb, to: Rgb, t: f64) -> Rgb {
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// lowlands green, hills brown, peaks white
+fn height_colour(height: i32) -> Rgb {
+    const STOPS: [Rgb; 3] = [(34, 102, 51), (153, 119, 68), (245, 245, 240)];
+    let t = height.clamp(0, 9) as f64 / 9.0 * 2.0;
+    if t <= 1.0 {
+        lerp(STOPS[0], STOPS[1], t)
+    } else {
+        lerp(STOPS[1], STOPS[2], t - 1.0)
+    }
+}
+
+const BLOCKED_COLOUR: Rgb = (30, 30, 30);
+
+// pale yellow for the lowest values up to deep red for the highest
+fn overlay_colour(value: i32, max: i32) -> Rgb {
+    let t = if max > 0 {
+        value as f64 / max as f64
+    } else {
+        1.0
+    };
+    lerp((255, 230, 80), (200, 20, 20), t)
+}
+
+fn render_ansi(map: &Map, overlay: &Overlay) -> String {
+    let mut out = String::new();
+    for y in 0..map.height() as i32 {
+        for x in 0..map.width() as i32 {
+            let pos = IVec2D::from((x, y));
+            let Some(height) = map.get(pos) else {
+                let (r, g, b) = BLOCKED_COLOUR;
+                out += &format!("\x1b[48;2;{};{};{}m  ", r, g, b);
                 continue;
+            };
+
+            let (r, g, b) = height_colour(height);
+            out += &format!("\x1b[48;2;{};{};{}m", r, g, b);
+            let marker = if let Some(&value) = overlay.trail_heads.get(&pos) {
+                Some(('o', overlay_colour(value, overlay.max_trail_head())))
+            } else {
+                overlay
+                    .summits
+                    .get(&pos)
+                    .map(|&value| ('^', overlay_colour(value, overlay.max_summit())))
+            };
+            match marker {
+                Some((symbol, (r, g, b))) => {
+                    out += &format!("\x1b[1;38;2;{};{};{}m{} \x1b[22m", r, g, b, symbol)
+                }
+                None => out += "  ",
             }
-            let score = dfs(x as i32, y as i32, map, unique);
+        }
+        out += "\x1b[0m\n";
+    }
+    out
+}
+
+const SVG_CELL_SIZE: i32 = 12;
+
+fn render_svg(map: &Map, overlay: &Overlay) -> String {
+    let width = map.width() as i32 * SVG_CELL_SIZE;
+    let height = map.height() as i32 * SVG_CELL_SIZE;
+    let half = SVG_CELL_SIZE / 2;
 
-            all_trail_heads.push(TrailHead {
-                pos: (x as i32, y as i32).into(),
-                score,
-            });
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+        width, height, width, height
+    );
+
+    for y in 0..map.height() as i32 {
+        for x in 0..map.width() as i32 {
+            let pos = IVec2D::from((x, y));
+            let (colour, title) = match map.get(pos) {
+                Some(height) => (height_colour(height), format!("height {}", height)),
+                None => (BLOCKED_COLOUR, "blocked".to_string()),
+            };
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"rgb({}, {}, {})\"><title>{}</title></rect>\n",
+                x * SVG_CELL_SIZE,
+                y * SVG_CELL_SIZE,
+                SVG_CELL_SIZE,
+                SVG_CELL_SIZE,
+                colour.0,
+                colour.1,
+                colour.2,
+                title
+            );
         }
     }
 
-    all_trail_heads
+    let mut trail_heads: Vec<_> = overlay.trail_heads.iter().collect();
+    trail_heads.sort_by_key(|(pos, _)| (pos.y, pos.x));
+    for (pos, &value) in trail_heads {
+        let (r, g, b) = overlay_colour(value, overlay.max_trail_head());
+        svg += &format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"rgb({}, {}, {})\" stroke=\"black\"><title>trailhead ({}, {}): {}</title></circle>\n",
+            pos.x * SVG_CELL_SIZE + half,
+            pos.y * SVG_CELL_SIZE + half,
+            half - 1,
+            r,
+            g,
+            b,
+            pos.x,
+            pos.y,
+            value
+        );
+    }
+
+    let mut summits: Vec<_> = overlay.summits.iter().collect();
+    summits.sort_by_key(|(pos, _)| (pos.y, pos.x));
+    for (pos, &value) in summits {
+        let (r, g, b) = overlay_colour(value, overlay.max_summit());
+        let (left, top) = (pos.x * SVG_CELL_SIZE, pos.y * SVG_CELL_SIZE);
+        svg += &format!(
+            "<polygon points=\"{},{} {},{} {},{}\" fill=\"rgb({}, {}, {})\" stroke=\"black\"><title>summit ({}, {}): reached from {} trailheads</title></polygon>\n",
+            left + half,
+            top + 1,
+            left + SVG_CELL_SIZE - 1,
+            top + SVG_CELL_SIZE - 1,
+            left + 1,
+            top + SVG_CELL_SIZE - 1,
+            r,
+            g,
+            b,
+            pos.x,
+            pos.y,
+            value
+        );
+    }
+
+    svg += "</svg>\n";
+    svg
 }
 
 fn part01() {
     let input = get_input("input.txt");
     let map = parse_input(input);
-    println!("{}", map);
 
-    let trail_heads = find_all_trailhead_scores(&map, false);
-    let sum = trail_heads
-        .iter()
-        .map(|th| {
-            println!("{:?}", th);
-            th.score
-        })
-        .sum::<i32>();
+    let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+    let sum = trail_heads.iter().map(|th| th.score).sum::<i32>();
     println!("{}", sum);
 }
 
 fn part02() {
     let input = get_input("input.txt");
     let map = parse_input(input);
-    println!("{}", map);
 
-    let trail_heads = find_all_trailhead_scores(&map, true);
-    let sum = trail_heads
-        .iter()
-        .map(|th| {
-            println!("{:?}", th);
-            th.score
-        })
-        .sum::<i32>();
+    let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+    let sum = trail_heads.iter().map(|th| th.rating).sum::<i32>();
     println!("{}", sum);
 }
 
+fn trails() {
+    let input = get_input("example.txt");
+    let map = parse_input(input);
+
+    let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+    let best = trail_heads.iter().max_by_key(|th| th.rating).unwrap();
+    let trails = find_trails(&map, &TrailRules::default(), best.pos, Some(3));
+    println!("{} of {} trails from {}", trails.len(), best.rating, best);
+    print!("{}", render_trails(&map, &trails));
+
+    std::fs::write(
+        "trails.json",
+        trails_to_json(&find_trails(&map, &TrailRules::default(), best.pos, None)),
+    )
+    .unwrap();
+}
+
+fn render() {
+    let input = get_input("input.txt");
+    let map = parse_input(input);
+    let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+
+    print!(
+        "{}",
+        render_ansi(&map, &Overlay::new(&trail_heads, Metric::Score))
+    );
+    std::fs::write(
+        "heightmap.svg",
+        render_svg(&map, &Overlay::new(&trail_heads, Metric::Rating)),
+    )
+    .unwrap();
+}
+
 fn main() {
     part01();
     part02();
+    trails();
+    render();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(rows: &[&str]) -> Map {
+        parse_input(rows.join("\n"))
+    }
+
+    fn transpose(map: &Map) -> Map {
+        Map((0..map.width())
+            .map(|x| map.iter().map(|row| row[x]).collect())
+            .collect())
+    }
+
+    fn totals(map: &Map) -> (i32, i32) {
+        let trail_heads = find_all_trailheads(map, &TrailRules::default());
+        let score = trail_heads.iter().map(|th| th.score).sum();
+        let rating = trail_heads.iter().map(|th| th.rating).sum();
+        (score, rating)
+    }
+
+    #[test]
+    fn get_is_row_major() {
+        let map = map(&["0123", "4567"]);
+        assert_eq!(map.width(), 4);
+        assert_eq!(map.height(), 2);
+        assert_eq!(map.get((3, 0).into()), Some(3));
+        assert_eq!(map.get((0, 1).into()), Some(4));
+        assert_eq!(map.get((3, 1).into()), Some(7));
+    }
+
+    #[test]
+    fn get_out_of_bounds() {
+        let map = map(&["0123", "4567"]);
+        assert_eq!(map.get((4, 0).into()), None);
+        assert_eq!(map.get((0, 2).into()), None);
+        assert_eq!(map.get((-1, 0).into()), None);
+        assert_eq!(map.get((0, -1).into()), None);
+    }
+
+    #[test]
+    fn single_row() {
+        let map = map(&["0123456789"]);
+        assert_eq!(totals(&map), (1, 1));
+        assert_eq!(totals(&transpose(&map)), (1, 1));
+    }
+
+    #[test]
+    fn wide_map_crosses_rows() {
+        let map = map(&["0123456789", "9876543210"]);
+        let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+        let positions: Vec<IVec2D> = trail_heads.iter().map(|th| th.pos).collect();
+        assert_eq!(positions, vec![(0, 0).into(), (9, 1).into()]);
+        assert_eq!(totals(&map), (4, 4));
+    }
+
+    #[test]
+    fn tall_map_crosses_columns() {
+        let map = transpose(&map(&["0123456789", "9876543210"]));
+        assert_eq!(map.width(), 2);
+        assert_eq!(map.height(), 10);
+        assert_eq!(totals(&map), (4, 4));
+    }
+
+    #[test]
+    fn example() {
+        let map = parse_input(get_input("example.txt"));
+        assert_eq!(totals(&map), (36, 81));
+    }
+
+    #[test]
+    fn many_paths_one_summit() {
+        let map = map(&["0123", "1234", "2345", "3456", "4567", "5678", "6789"]);
+        let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+        assert_eq!(trail_heads.len(), 1);
+        assert_eq!(trail_heads[0].score, 1);
+        // choosing when to take the 3 steps right among the 9 steps
+        assert_eq!(trail_heads[0].rating, 84);
+    }
+
+    #[test]
+    fn trails_match_rating() {
+        let map = parse_input(get_input("example.txt"));
+        for th in find_all_trailheads(&map, &TrailRules::default()) {
+            let trails = find_trails(&map, &TrailRules::default(), th.pos, None);
+            assert_eq!(trails.len() as i32, th.rating);
+
+            let summits: HashSet<IVec2D> = trails.iter().map(|t| *t.last().unwrap()).collect();
+            assert_eq!(summits.len() as i32, th.score);
+            for trail in trails {
+                assert_eq!(trail.len(), 10);
+                assert_eq!(trail[0], th.pos);
+                for (height, &pos) in trail.iter().enumerate() {
+                    assert_eq!(map.get(pos), Some(height as i32));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trails_limit() {
+        let map = map(&["0123", "1234", "2345", "3456", "4567", "5678", "6789"]);
+        assert_eq!(
+            find_trails(&map, &TrailRules::default(), (0, 0).into(), Some(5)).len(),
+            5
+        );
+        assert_eq!(
+            find_trails(&map, &TrailRules::default(), (0, 0).into(), Some(0)).len(),
+            0
+        );
+        assert_eq!(
+            find_trails(&map, &TrailRules::default(), (1, 0).into(), None).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn trails_export() {
+        let row = map(&["0123456789"]);
+        let trails = find_trails(&row, &TrailRules::default(), (0, 0).into(), None);
+        let json = trails_to_json(&trails);
+        assert!(json.starts_with("{\"trails\":[[{\"x\":0,\"y\":0},{\"x\":1,\"y\":0}"));
+        assert_eq!(render_trails(&row, &trails), "trail 1:\n0123456789\n");
+
+        let crossing = map(&["0123456789", "9876543210"]);
+        let trails = find_trails(&crossing, &TrailRules::default(), (0, 0).into(), None);
+        assert_eq!(
+            render_trails(&crossing, &trails),
+            "trail 1:\n0123456789\n..........\ntrail 2:\n01234.....\n98765.....\n"
+        );
+    }
+
+    #[test]
+    fn blocked_tiles() {
+        let two_summits = map(&[
+            "...0...", "...1...", "...2...", "6543456", "7.....7", "8.....8", "9.....9",
+        ]);
+        assert_eq!(two_summits.get((0, 0).into()), None);
+        assert_eq!(two_summits.to_string().lines().next(), Some("...0..."));
+        assert_eq!(totals(&two_summits), (2, 2));
+
+        let four_summits = map(&[
+            "..90..9", "...1.98", "...2..7", "6543456", "765.987", "876....", "987....",
+        ]);
+        assert_eq!(totals(&four_summits).0, 4);
+
+        let three_trails = map(&[
+            ".....0.", "..4321.", "..5..2.", "..6543.", "..7..4.", "..8765.", "..9....",
+        ]);
+        assert_eq!(totals(&three_trails), (1, 3));
+    }
+
+    #[test]
+    fn larger_ascents() {
+        let map = map(&["013579"]);
+        let rules = TrailRules {
+            max_ascent: 2,
+            ..Default::default()
+        };
+        assert_eq!(totals(&map), (0, 0));
+        let trail_heads = find_all_trailheads(&map, &rules);
+        assert_eq!((trail_heads[0].score, trail_heads[0].rating), (1, 1));
+
+        // the sweep and walking every trail agree
+        let map = parse_input(get_input("example.txt"));
+        for th in find_all_trailheads(&map, &rules) {
+            let trails = find_trails(&map, &rules, th.pos, None);
+            assert_eq!(trails.len() as i32, th.rating);
+        }
+    }
+
+    #[test]
+    fn descents() {
+        let map = map(&["012323456789"]);
+        let rules = TrailRules {
+            max_descent: 1,
+            ..Default::default()
+        };
+        assert_eq!(totals(&map), (0, 0));
+        let trail_heads = find_all_trailheads(&map, &rules);
+        assert_eq!((trail_heads[0].score, trail_heads[0].rating), (1, 1));
+        assert_eq!(find_trails(&map, &rules, (0, 0).into(), None)[0].len(), 12);
+    }
+
+    #[test]
+    fn diagonals_and_heights() {
+        let map = map(&["3.5", ".4."]);
+        let rules = TrailRules {
+            start: 3,
+            summit: 5,
+            ..Default::default()
+        };
+        let trail_heads = find_all_trailheads(&map, &rules);
+        assert_eq!(trail_heads.len(), 1);
+        assert_eq!(trail_heads[0].rating, 0);
+
+        let rules = TrailRules {
+            diagonals: true,
+            ..rules
+        };
+        let trail_heads = find_all_trailheads(&map, &rules);
+        assert_eq!((trail_heads[0].score, trail_heads[0].rating), (1, 1));
+    }
+
+    #[test]
+    fn overlay_counts() {
+        let map = map(&["0123456789", "9876543210"]);
+        let trail_heads = find_all_trailheads(&map, &TrailRules::default());
+        let overlay = Overlay::new(&trail_heads, Metric::Rating);
+        assert_eq!(overlay.trail_heads[&(0, 0).into()], 2);
+        assert_eq!(overlay.summits[&(9, 0).into()], 2);
+        assert_eq!(overlay.summits[&(0, 1).into()], 2);
+        assert_eq!(overlay.summits.len(), 2);
+
+        let ansi = render_ansi(&map, &overlay);
+        assert_eq!(ansi.lines().count(), 2);
+        assert_eq!(ansi.matches('o').count(), 2);
+        assert_eq!(ansi.matches('^').count(), 2);
+
+        let svg = render_svg(&map, &overlay);
+        assert_eq!(svg.matches("<rect").count(), 20);
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert_eq!(svg.matches("<polygon").count(), 2);
+    }
+
+    #[test]
+    fn example_cropped() {
+        let map = parse_input(get_input("example.txt"));
+        let wide = Map(map[..3].to_vec());
+        let tall = Map(map.iter().map(|row| row[..3].to_vec()).collect());
+        assert_eq!(totals(&wide), totals(&transpose(&wide)));
+        assert_eq!(totals(&tall), totals(&transpose(&tall)));
+    }
 }
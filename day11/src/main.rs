@@ -1,75 +1,1097 @@
-use std::{collections::HashMap, hash::Hash, ops::Deref};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+#[cfg(not(feature = "wide"))]
+type Stone = u64;
+#[cfg(feature = "wide")]
+type Stone = u128;
+
+// how many stones carry the same value
+type Count = Stone;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlinkError {
+    // transforming this stone does not fit in a `Stone`
+    Value(Stone),
+    // the number of stones with this value does not fit in a `Count`
+    Count(Stone),
+    // a split rule matched a stone whose digits do not divide into equal parts
+    Unsplittable { stone: Stone, parts: u32 },
+}
+
+impl fmt::Display for BlinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlinkError::Value(stone) => write!(f, "stone {} overflows when transformed", stone),
+            BlinkError::Count(stone) => {
+                write!(f, "too many stones with value {} to count", stone)
+            }
+            BlinkError::Unsplittable { stone, parts } => {
+                write!(f, "stone {} cannot be split into {} parts", stone, parts)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Predicate {
+    Any,
+    Equals(Stone),
+    Digits(u32),
+    DigitsMultipleOf(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Set(Stone),
+    Mul(Stone),
+    Add(Stone),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Transform {
+    // cut the digits into this many equal parts, most significant first
+    Split(u32),
+    // replace the stone by a single one, applying the ops in order
+    Replace(Vec<Op>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    when: Predicate,
+    then: Transform,
+}
+
+// the rules are tried from top to bottom and the first one that matches is
+// applied, a stone that matches no rule stays as it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RuleSet {
+    base: Stone,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RuleParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::Any => write!(f, "any"),
+            Predicate::Equals(value) => write!(f, "eq {}", value),
+            Predicate::Digits(digits) => write!(f, "digits {}", digits),
+            Predicate::DigitsMultipleOf(n) => write!(f, "digits-multiple-of {}", n),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transform::Split(parts) => write!(f, "split {}", parts),
+            Transform::Replace(ops) => {
+                let ops: Vec<String> = ops
+                    .iter()
+                    .map(|op| match op {
+                        Op::Set(v) => format!("set {}", v),
+                        Op::Mul(v) => format!("mul {}", v),
+                        Op::Add(v) => format!("add {}", v),
+                    })
+                    .collect();
+                write!(f, "{}", ops.join(" "))
+            }
+        }
+    }
+}
+
+// writes the rule set back in the format `RuleSet::parse` reads
+impl fmt::Display for RuleSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "base {}", self.base)?;
+        for rule in &self.rules {
+            writeln!(f, "{} -> {}", rule.when, rule.then)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+    let word = word.ok_or("missing number")?;
+    word.parse()
+        .map_err(|_| format!("'{}' is not a valid number", word))
+}
+
+// predicate := "any" | "zero" | "eq" N | "digits" N | "even-digits" | "digits-multiple-of" N
+fn parse_predicate(text: &str) -> Result<Predicate, String> {
+    let mut words = text.split_whitespace();
+    let predicate = match words.next() {
+        Some("any") => Predicate::Any,
+        Some("zero") => Predicate::Equals(0),
+        Some("eq") => Predicate::Equals(parse_number(words.next())?),
+        Some("digits") => Predicate::Digits(parse_number(words.next())?),
+        Some("even-digits") => Predicate::DigitsMultipleOf(2),
+        Some("digits-multiple-of") => Predicate::DigitsMultipleOf(parse_number(words.next())?),
+        Some(other) => return Err(format!("unknown predicate '{}'", other)),
+        None => return Err("missing predicate".to_string()),
+    };
+    match words.next() {
+        Some(extra) => Err(format!("unexpected '{}' after predicate", extra)),
+        None => Ok(predicate),
+    }
+}
+
+// transform := "split" N | op+
+// op        := "set" N | "mul" N | "add" N
+fn parse_transform(text: &str) -> Result<Transform, String> {
+    let mut words = text.split_whitespace().peekable();
+    if words.peek() == Some(&"split") {
+        words.next();
+        let parts: u32 = parse_number(words.next())?;
+        if parts < 2 {
+            return Err("a split needs at least 2 parts".to_string());
+        }
+        return match words.next() {
+            Some(extra) => Err(format!("unexpected '{}' after split", extra)),
+            None => Ok(Transform::Split(parts)),
+        };
+    }
+
+    let mut ops = Vec::new();
+    while let Some(word) = words.next() {
+        let op = match word {
+            "set" => Op::Set(parse_number(words.next())?),
+            "mul" => Op::Mul(parse_number(words.next())?),
+            "add" => Op::Add(parse_number(words.next())?),
+            other => return Err(format!("unknown operation '{}'", other)),
+        };
+        ops.push(op);
+    }
+    if ops.is_empty() {
+        return Err("missing transform".to_string());
+    }
+    Ok(Transform::Replace(ops))
+}
+
+impl RuleSet {
+    // the rules from the puzzle
+    fn standard() -> Self {
+        Self::parse(include_str!("../rules.txt")).unwrap()
+    }
+
+    // base B
+    // predicate -> transform
+    //
+    // one entry per line, blank lines and everything after '#' are ignored
+    fn parse(text: &str) -> Result<Self, RuleParseError> {
+        let mut base = 10;
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| RuleParseError {
+                line: i + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix("base ") {
+                base = parse_number(Some(value.trim())).map_err(error)?;
+                if base < 2 {
+                    return Err(error("base must be at least 2".to_string()));
+                }
+                continue;
+            }
+
+            let (when, then) = line
+                .split_once("->")
+                .ok_or_else(|| error("expected 'predicate -> transform'".to_string()))?;
+            rules.push(Rule {
+                when: parse_predicate(when).map_err(error)?,
+                then: parse_transform(then).map_err(error)?,
+            });
+        }
+        Ok(Self { base, rules })
+    }
+
+    fn load(file_path: &str) -> Result<Self, RuleParseError> {
+        Self::parse(&get_input(file_path))
+    }
+
+    fn count_digits(&self, stone: Stone) -> u32 {
+        if stone == 0 {
+            1
+        } else {
+            stone.ilog(self.base) + 1
+        }
+    }
+
+    fn matches(&self, predicate: Predicate, stone: Stone) -> bool {
+        match predicate {
+            Predicate::Any => true,
+            Predicate::Equals(value) => stone == value,
+            Predicate::Digits(digits) => self.count_digits(stone) == digits,
+            Predicate::DigitsMultipleOf(n) => self.count_digits(stone).is_multiple_of(n),
+        }
+    }
+
+    // blinks a single stone, handing every resulting stone to `emit`
+    fn apply<F>(&self, stone: Stone, mut emit: F) -> Result<(), BlinkError>
+    where
+        F: FnMut(Stone) -> Result<(), BlinkError>,
+    {
+        let Some(rule) = self
+            .rules
+            .iter()
+            .find(|rule| self.matches(rule.when, stone))
+        else {
+            return emit(stone);
+        };
+
+        match &rule.then {
+            &Transform::Split(parts) => {
+                let digits = self.count_digits(stone);
+                if !digits.is_multiple_of(parts) {
+                    return Err(BlinkError::Unsplittable { stone, parts });
+                }
+                let part_size = self.base.pow(digits / parts);
+                let mut divisor = self.base.pow(digits - digits / parts);
+                for _ in 0..parts {
+                    emit(stone / divisor % part_size)?;
+                    divisor /= part_size;
+                }
+                Ok(())
+            }
+            Transform::Replace(ops) => {
+                let mut value = stone;
+                for op in ops {
+                    value = match *op {
+                        Op::Set(v) => Some(v),
+                        Op::Mul(v) => value.checked_mul(v),
+                        Op::Add(v) => value.checked_add(v),
+                    }
+                    .ok_or(BlinkError::Value(stone))?;
+                }
+                emit(value)
+            }
+        }
+    }
+}
 
 fn get_input(file_path: &str) -> String {
     std::fs::read_to_string(file_path).unwrap()
 }
 
-fn parse_input(input: &str) -> Vec<u64> {
+fn parse_input(input: &str) -> Vec<Stone> {
     input
         .split_whitespace()
-        .map(|s| s.parse::<u64>().unwrap())
+        .map(|s| s.parse::<Stone>().unwrap())
         .collect()
 }
 
-fn blink_stones(stones: &[u64]) -> Vec<u64> {
-    let mut result: Vec<u64> = Vec::new();
+fn blink_stones(rules: &RuleSet, stones: &[Stone]) -> Result<Vec<Stone>, BlinkError> {
+    let mut result: Vec<Stone> = Vec::with_capacity(stones.len() * 2);
 
     for &stone in stones {
-        let digits = stone.to_string().chars().collect::<Vec<char>>();
-        if stone == 0 {
-            result.push(1);
-        } else if digits.len() % 2 == 0 {
-            let left = digits[0..digits.len() / 2].iter().collect::<String>();
-            let right = digits[digits.len() / 2..].iter().collect::<String>();
-
-            let left_val = left.parse::<u64>().unwrap();
-            let right_val = right.parse::<u64>().unwrap();
-            result.push(left_val);
-            result.push(right_val);
-        } else {
-            result.push(stone * 2024);
+        rules.apply(stone, |stone| {
+            result.push(stone);
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+fn add_stones(
+    stones_map: &mut HashMap<Stone, Count>,
+    stone: Stone,
+    count: Count,
+) -> Result<(), BlinkError> {
+    let total = stones_map.entry(stone).or_insert(0);
+    *total = total.checked_add(count).ok_or(BlinkError::Count(stone))?;
+    Ok(())
+}
+
+fn blink_stones_batched(
+    rules: &RuleSet,
+    stones_map: &HashMap<Stone, Count>,
+) -> Result<HashMap<Stone, Count>, BlinkError> {
+    let mut result: HashMap<Stone, Count> = HashMap::with_capacity(stones_map.len());
+    for (&stone, &count) in stones_map {
+        rules.apply(stone, |next| add_stones(&mut result, next, count))?;
+    }
+
+    Ok(result)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BlinkStats {
+    blink: u32,
+    distinct: usize,
+    total: Count,
+    // number of stones for each digit length
+    digit_histogram: BTreeMap<u32, Count>,
+}
+
+impl BlinkStats {
+    fn new(
+        rules: &RuleSet,
+        blink: u32,
+        stones_map: &HashMap<Stone, Count>,
+    ) -> Result<Self, BlinkError> {
+        let mut total: Count = 0;
+        let mut digit_histogram = BTreeMap::new();
+        for (&stone, &count) in stones_map {
+            total = total.checked_add(count).ok_or(BlinkError::Count(stone))?;
+            let bucket = digit_histogram
+                .entry(rules.count_digits(stone))
+                .or_insert(0 as Count);
+            *bucket = bucket.checked_add(count).ok_or(BlinkError::Count(stone))?;
+        }
+        Ok(Self {
+            blink,
+            distinct: stones_map.len(),
+            total,
+            digit_histogram,
+        })
+    }
+}
+
+// every value a blink can produce from the starting stones
+struct Closure {
+    // the first blink that produced no new value
+    blink: u32,
+    values: HashSet<Stone>,
+}
+
+struct Population {
+    stats: Vec<BlinkStats>,
+    closure: Option<Closure>,
+}
+
+// once a blink only yields values seen before, the successors of all of those
+// have been seen as well, so the set of values can never grow again
+fn analyse_population(
+    rules: &RuleSet,
+    stones: &[Stone],
+    blinks: u32,
+) -> Result<Population, BlinkError> {
+    let mut map: HashMap<Stone, Count> = HashMap::new();
+    for &stone in stones {
+        add_stones(&mut map, stone, 1)?;
+    }
+    let mut seen: HashSet<Stone> = map.keys().copied().collect();
+    let mut stats = vec![BlinkStats::new(rules, 0, &map)?];
+    let mut closure = None;
+
+    for blink in 1..=blinks {
+        map = blink_stones_batched(rules, &map)?;
+        stats.push(BlinkStats::new(rules, blink, &map)?);
+
+        if closure.is_none() {
+            let before = seen.len();
+            seen.extend(map.keys().copied());
+            if seen.len() == before {
+                closure = Some(Closure {
+                    blink,
+                    values: std::mem::take(&mut seen),
+                });
+            }
+        }
+    }
+    Ok(Population { stats, closure })
+}
+
+// a square matrix with entries reduced modulo `modulus`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ModMatrix {
+    size: usize,
+    modulus: u64,
+    entries: Vec<u64>,
+}
+
+impl ModMatrix {
+    fn zero(size: usize, modulus: u64) -> Self {
+        Self {
+            size,
+            modulus,
+            entries: vec![0; size * size],
+        }
+    }
+
+    // how many products fit in a u128 before the sum has to be reduced
+    fn lazy_terms(&self) -> usize {
+        let max_product = ((self.modulus - 1) as u128 * (self.modulus - 1) as u128).max(1);
+        (u128::MAX / max_product).min(usize::MAX as u128) as usize
+    }
+
+    fn mul(&self, other: &ModMatrix) -> ModMatrix {
+        let n = self.size;
+        let lazy_terms = self.lazy_terms();
+        let mut entries = vec![0; n * n];
+        let modulus = self.modulus as u128;
+        let mut row = vec![0u128; n];
+        for i in 0..n {
+            row.fill(0);
+            for k in 0..n {
+                let a = self.entries[i * n + k] as u128;
+                if a != 0 {
+                    let other_row = &other.entries[k * n..(k + 1) * n];
+                    for (sum, &b) in row.iter_mut().zip(other_row) {
+                        *sum += a * b as u128;
+                    }
+                }
+                if (k + 1) % lazy_terms == 0 {
+                    row.iter_mut().for_each(|sum| *sum %= modulus);
+                }
+            }
+            for (entry, sum) in entries[i * n..(i + 1) * n].iter_mut().zip(&row) {
+                *entry = (sum % modulus) as u64;
+            }
+        }
+        ModMatrix {
+            size: n,
+            modulus: self.modulus,
+            entries,
+        }
+    }
+
+    // the row vector `vector` multiplied by this matrix
+    fn mul_vector(&self, vector: &[u64]) -> Vec<u64> {
+        let n = self.size;
+        let mut result = vec![0u64; n];
+        for (i, &a) in vector.iter().enumerate() {
+            if a != 0 {
+                for (sum, &b) in result.iter_mut().zip(&self.entries[i * n..(i + 1) * n]) {
+                    *sum = add_mod(*sum, mul_mod(a, b, self.modulus), self.modulus);
+                }
+            }
+        }
+        result
+    }
+}
+
+// `Count` is u128 with the wide feature
+#[allow(clippy::unnecessary_cast)]
+fn count_to_u64(count: Count) -> u64 {
+    count as u64
+}
+
+// every value reachable from the starting stones
+fn reachable_values(rules: &RuleSet, stones: &[Stone]) -> Result<Vec<Stone>, BlinkError> {
+    let mut seen: HashSet<Stone> = stones.iter().copied().collect();
+    let mut values: Vec<Stone> = seen.iter().copied().collect();
+    let mut next = 0;
+    while next < values.len() {
+        let stone = values[next];
+        next += 1;
+        rules.apply(stone, |child| {
+            if seen.insert(child) {
+                values.push(child);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(values)
+}
+
+// the most values on a cycle for which `count_mod` exponentiates the dense
+// matrix, each multiplication is cubic in their number
+const MATRIX_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModulusError {
+    Zero,
+    // the modulus is divisible by the square of `prime` and `recurrent` values
+    // lie on a cycle, more than MATRIX_LIMIT
+    RepeatedFactor { prime: u64, recurrent: usize },
+}
+
+impl fmt::Display for ModulusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModulusError::Zero => write!(f, "the modulus must not be zero"),
+            ModulusError::RepeatedFactor { prime, recurrent } => write!(
+                f,
+                "the modulus is divisible by {}^2, which is only supported with at most {} values on a cycle, not {}",
+                prime, MATRIX_LIMIT, recurrent
+            ),
+        }
+    }
+}
+
+// one blink as a linear map on the vector of counts per closure value, entry
+// (i, j) is how many stones of value j a single stone of value i becomes
+struct TransitionMatrix {
+    values: Vec<Stone>,
+    successors: Vec<Vec<(usize, u64)>>,
+}
+
+impl TransitionMatrix {
+    fn new(rules: &RuleSet, stones: &[Stone]) -> Result<Self, BlinkError> {
+        let values = reachable_values(rules, stones)?;
+        let index: HashMap<Stone, usize> =
+            values.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+
+        let mut successors = Vec::with_capacity(values.len());
+        for &stone in &values {
+            let next = blink_stones_batched(rules, &HashMap::from([(stone, 1)]))?;
+            successors.push(
+                next.iter()
+                    .map(|(value, &count)| (index[value], count_to_u64(count)))
+                    .collect(),
+            );
+        }
+        Ok(Self { values, successors })
+    }
+
+    fn counts(&self, stones: &[Stone], modulus: u64) -> Vec<u64> {
+        let mut counts = vec![0; self.values.len()];
+        for &stone in stones {
+            let i = self.values.iter().position(|&v| v == stone).unwrap();
+            counts[i] = add_mod(counts[i], 1, modulus);
+        }
+        counts
+    }
+
+    fn step(&self, counts: &[u64], modulus: u64) -> Vec<u64> {
+        let mut next = vec![0; counts.len()];
+        for (i, &count) in counts.iter().enumerate() {
+            for &(j, times) in &self.successors[i] {
+                next[j] = add_mod(next[j], mul_mod(count, times % modulus, modulus), modulus);
+            }
+        }
+        next
+    }
+
+    // peels off the values no cycle leads to, a stone of such a value is gone
+    // after at most `drain` blinks, returns the remaining values and `drain`
+    fn recurrent_part(&self) -> (Vec<usize>, u64) {
+        let n = self.values.len();
+        let mut in_degree = vec![0; n];
+        for successors in &self.successors {
+            for &(j, _) in successors {
+                in_degree[j] += 1;
+            }
+        }
+
+        let mut removed = vec![false; n];
+        let mut sources: Vec<usize> = (0..n).filter(|&i| in_degree[i] == 0).collect();
+        let mut drain = 0;
+        while !sources.is_empty() {
+            drain += 1;
+            let mut next_sources = Vec::new();
+            for i in sources {
+                removed[i] = true;
+                for &(j, _) in &self.successors[i] {
+                    in_degree[j] -= 1;
+                    if in_degree[j] == 0 {
+                        next_sources.push(j);
+                    }
+                }
+            }
+            sources = next_sources;
+        }
+        ((0..n).filter(|&i| !removed[i]).collect(), drain)
+    }
+
+    fn total(counts: &[u64], modulus: u64) -> u64 {
+        counts
+            .iter()
+            .fold(0, |acc, &count| add_mod(acc, count, modulus))
+    }
+
+    // how many stones there are after `blinks` blinks, modulo `modulus`.
+    // beyond a few blinks this works per prime factor of the modulus, so a
+    // modulus with a repeated prime factor is only supported when few enough
+    // values lie on a cycle to exponentiate the dense matrix instead
+    fn count_mod(&self, stones: &[Stone], blinks: u64, modulus: u64) -> Result<u64, ModulusError> {
+        if modulus == 0 {
+            return Err(ModulusError::Zero);
+        }
+        let size = self.values.len() as u64;
+        if blinks <= 2 * size {
+            let mut counts = self.counts(stones, modulus);
+            for _ in 0..blinks {
+                counts = self.step(&counts, modulus);
+            }
+            return Ok(Self::total(&counts, modulus));
+        }
+
+        let factors = factorise(modulus);
+        let Some(prime) = factors
+            .windows(2)
+            .find(|pair| pair[0] == pair[1])
+            .map(|pair| pair[0])
+        else {
+            return Ok(self.count_by_recurrence(stones, blinks, &factors));
+        };
+
+        let (recurrent, _) = self.recurrent_part();
+        if recurrent.len() > MATRIX_LIMIT {
+            return Err(ModulusError::RepeatedFactor {
+                prime,
+                recurrent: recurrent.len(),
+            });
+        }
+        Ok(self.count_by_matrix_power(stones, blinks, modulus))
+    }
+
+    // the first few blinks are simulated until only values on or after a cycle
+    // are left, the rest is fast exponentiation of the matrix restricted to them
+    fn count_by_matrix_power(&self, stones: &[Stone], blinks: u64, modulus: u64) -> u64 {
+        let mut counts = self.counts(stones, modulus);
+        let (recurrent, drain) = self.recurrent_part();
+        for _ in 0..drain.min(blinks) {
+            counts = self.step(&counts, modulus);
+        }
+        if blinks <= drain {
+            return Self::total(&counts, modulus);
+        }
+
+        let size = recurrent.len();
+        let mut position = vec![usize::MAX; self.values.len()];
+        for (p, &i) in recurrent.iter().enumerate() {
+            position[i] = p;
+        }
+        let mut matrix = ModMatrix::zero(size, modulus);
+        for (p, &i) in recurrent.iter().enumerate() {
+            for &(j, times) in &self.successors[i] {
+                let entry = &mut matrix.entries[p * size + position[j]];
+                *entry = add_mod(*entry, times % modulus, modulus);
+            }
+        }
+
+        let mut vector: Vec<u64> = recurrent.iter().map(|&i| counts[i]).collect();
+        let mut exponent = blinks - drain;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                vector = matrix.mul_vector(&vector);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                matrix = matrix.mul(&matrix);
+            }
+        }
+        Self::total(&vector, modulus)
+    }
+
+    // the totals satisfy a linear recurrence no longer than the matrix is wide
+    // (its characteristic polynomial), modulo a prime it can be recovered from
+    // twice that many totals and then raised to `blinks` as a polynomial,
+    // which is the same as exponentiating the much smaller companion matrix.
+    // the counts modulo each of the distinct `primes` are combined into the
+    // count modulo their product by chinese remaindering
+    fn count_by_recurrence(&self, stones: &[Stone], blinks: u64, primes: &[u64]) -> u64 {
+        let mut result = 0;
+        let mut product = 1;
+        for &prime in primes {
+            let mut counts = self.counts(stones, prime);
+            let mut totals = Vec::with_capacity(2 * self.values.len());
+            for _ in 0..2 * self.values.len() {
+                totals.push(Self::total(&counts, prime));
+                counts = self.step(&counts, prime);
+            }
+
+            let recurrence = berlekamp_massey(&totals, prime);
+            let coefficients = power_of_x(blinks, &recurrence, prime);
+            let count = coefficients
+                .iter()
+                .zip(&totals)
+                .fold(0, |acc, (&c, &total)| {
+                    add_mod(acc, mul_mod(c, total, prime), prime)
+                });
+
+            // result + product * t is the count modulo both product and prime
+            let t = mul_mod(
+                sub_mod(count, result % prime, prime),
+                mod_pow(product % prime, prime - 2, prime),
+                prime,
+            );
+            result += product * t;
+            product *= prime;
         }
+        result
+    }
+}
+
+// products modulo a u64 modulus, widened to u128 when they do not fit in a u64
+fn mul_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    if modulus <= 1 << 32 {
+        a * b % modulus
+    } else {
+        (a as u128 * b as u128 % modulus as u128) as u64
+    }
+}
+
+fn add_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 + b as u128) % modulus as u128) as u64
+}
+
+fn sub_mod(a: u64, b: u64, modulus: u64) -> u64 {
+    ((a as u128 + modulus as u128 - b as u128) % modulus as u128) as u64
+}
+
+fn mod_pow(mut base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, modulus);
+        }
+        base = mul_mod(base, base, modulus);
+        exponent >>= 1;
     }
     result
 }
 
-fn blink_stones_batched(stones_map: &HashMap<u64, u64>) -> HashMap<u64, u64> {
-    let mut result: HashMap<u64, u64> = HashMap::new();
-    for &stone in stones_map.keys() {
-        let digits = stone.to_string().chars().collect::<Vec<char>>();
-        if stone == 0 {
-            let count = result.entry(1).or_insert(0);
-            *count += stones_map.get(&stone).unwrap();
-        } else if digits.len() % 2 == 0 {
-            let left = digits[0..digits.len() / 2].iter().collect::<String>();
-            let right = digits[digits.len() / 2..].iter().collect::<String>();
+// deterministic miller-rabin, these bases are enough for every u64
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(&base) = BASES.iter().find(|&&base| n.is_multiple_of(base)) {
+        return n == base;
+    }
 
-            let left_val = left.parse::<u64>().unwrap();
-            let right_val = right.parse::<u64>().unwrap();
+    let shift = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> shift;
+    BASES.iter().all(|&base| {
+        let mut x = mod_pow(base, odd, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..shift {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
 
-            {
-                let left_count = result.entry(left_val).or_insert(0);
-                *left_count += stones_map.get(&stone).unwrap();
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// a non-trivial factor of the odd composite `n` by pollard's rho
+fn find_factor(n: u64) -> u64 {
+    for c in 1.. {
+        let step = |x: u64| add_mod(mul_mod(x, x, n), c, n);
+        let (mut x, mut y) = (2, 2);
+        loop {
+            x = step(x);
+            y = step(step(y));
+            let d = gcd(x.abs_diff(y), n);
+            if d == n {
+                break;
             }
-            {
-                let right_count = result.entry(right_val).or_insert(0);
-                *right_count += stones_map.get(&stone).unwrap();
+            if d != 1 {
+                return d;
             }
+        }
+    }
+    unreachable!()
+}
+
+// the prime factors of `n` with multiplicity, in ascending order
+fn factorise(n: u64) -> Vec<u64> {
+    let mut factors = Vec::new();
+    let mut rest = n;
+    for p in [2, 3, 5] {
+        while rest.is_multiple_of(p) {
+            factors.push(p);
+            rest /= p;
+        }
+    }
+
+    let mut pending = vec![rest];
+    while let Some(m) = pending.pop() {
+        if m == 1 {
+            continue;
+        }
+        if is_prime(m) {
+            factors.push(m);
         } else {
-            let count = result.entry(stone * 2024).or_insert(0);
-            *count += stones_map.get(&stone).unwrap();
+            let d = find_factor(m);
+            pending.extend([d, m / d]);
         }
     }
+    factors.sort_unstable();
+    factors
+}
+
+// the shortest recurrence s[n] = c[0] s[n - 1] + ... + c[L - 1] s[n - L]
+// that generates `sequence`, modulo a prime
+fn berlekamp_massey(sequence: &[u64], modulus: u64) -> Vec<u64> {
+    let mut current: Vec<u64> = Vec::new();
+    // the recurrence before the last length change, where it failed and by how much
+    let mut previous: Vec<u64> = Vec::new();
+    let mut previous_index = 0;
+    let mut previous_delta = 0;
+
+    for (n, &value) in sequence.iter().enumerate() {
+        let predicted = current.iter().enumerate().fold(0, |acc, (i, &c)| {
+            add_mod(acc, mul_mod(c, sequence[n - 1 - i], modulus), modulus)
+        });
+        let delta = sub_mod(value, predicted, modulus);
+        if delta == 0 {
+            continue;
+        }
+        if current.is_empty() {
+            current = vec![0; n + 1];
+            previous_index = n;
+            previous_delta = delta;
+            continue;
+        }
 
+        // correct with the previous recurrence, shifted and scaled so that it
+        // cancels the error at `n` without disturbing the terms before it
+        let factor = mul_mod(
+            delta,
+            mod_pow(previous_delta, modulus - 2, modulus),
+            modulus,
+        );
+        let mut next = vec![0; n - previous_index - 1];
+        next.push(factor);
+        next.extend(
+            previous
+                .iter()
+                .map(|&p| sub_mod(0, mul_mod(p, factor, modulus), modulus)),
+        );
+        if next.len() < current.len() {
+            next.resize(current.len(), 0);
+        }
+        for (x, &c) in next.iter_mut().zip(&current) {
+            *x = add_mod(*x, c, modulus);
+        }
+
+        if n - previous_index + previous.len() >= current.len() {
+            previous = std::mem::replace(&mut current, next);
+            previous_index = n;
+            previous_delta = delta;
+        } else {
+            current = next;
+        }
+    }
+    current
+}
+
+// x^exponent modulo the characteristic polynomial of `recurrence`, so that
+// s[exponent] is the dot product of the result with s[0..L]
+fn power_of_x(exponent: u64, recurrence: &[u64], modulus: u64) -> Vec<u64> {
+    let order = recurrence.len();
+    if order == 0 {
+        return Vec::new();
+    }
+
+    let mul = |a: &[u64], b: &[u64]| -> Vec<u64> {
+        let mut product = vec![0u64; 2 * order];
+        for (i, &x) in a.iter().enumerate() {
+            if x != 0 {
+                for (j, &y) in b.iter().enumerate() {
+                    product[i + j] = add_mod(product[i + j], mul_mod(x, y, modulus), modulus);
+                }
+            }
+        }
+        // x^L = c[0] x^(L - 1) + ... + c[L - 1]
+        for k in (order..2 * order).rev() {
+            let top = product[k];
+            if top != 0 {
+                for (i, &c) in recurrence.iter().enumerate() {
+                    product[k - 1 - i] =
+                        add_mod(product[k - 1 - i], mul_mod(top, c, modulus), modulus);
+                }
+            }
+        }
+        product.truncate(order);
+        product
+    };
+
+    let mut result = vec![0u64; order];
+    result[0] = 1 % modulus;
+    let mut base = vec![0u64; order];
+    if order == 1 {
+        base[0] = recurrence[0];
+    } else {
+        base[1] = 1;
+    }
+
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(&result, &base);
+        }
+        base = mul(&base, &base);
+        exponent >>= 1;
+    }
     result
 }
 
+#[derive(Debug)]
+enum CacheError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    // the cache was filled with a different rule set
+    RulesMismatch,
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::Io(err) => write!(f, "{}", err),
+            CacheError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            CacheError::RulesMismatch => write!(f, "cache was built for a different rule set"),
+        }
+    }
+}
+
+impl From<std::io::Error> for CacheError {
+    fn from(err: std::io::Error) -> Self {
+        CacheError::Io(err)
+    }
+}
+
+// remembers how many stones a single stone turns into after a number of
+// blinks, so queries for other blink counts and other inputs can reuse it
+struct StoneCache {
+    rules: RuleSet,
+    counts: HashMap<(Stone, u32), Count>,
+    hits: u64,
+    misses: u64,
+}
+
+impl StoneCache {
+    fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            counts: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn count_after(&mut self, stone: Stone, blinks: u32) -> Result<Count, BlinkError> {
+        if blinks == 0 {
+            return Ok(1);
+        }
+        if let Some(&count) = self.counts.get(&(stone, blinks)) {
+            self.hits += 1;
+            return Ok(count);
+        }
+        self.misses += 1;
+
+        let mut children = Vec::new();
+        self.rules.apply(stone, |child| {
+            children.push(child);
+            Ok(())
+        })?;
+
+        let mut count: Count = 0;
+        for child in children {
+            count = count
+                .checked_add(self.count_after(child, blinks - 1)?)
+                .ok_or(BlinkError::Count(stone))?;
+        }
+        self.counts.insert((stone, blinks), count);
+        Ok(count)
+    }
+
+    fn count_stones(&mut self, stones: &[Stone], blinks: u32) -> Result<Count, BlinkError> {
+        stones.iter().try_fold(0 as Count, |total, &stone| {
+            total
+                .checked_add(self.count_after(stone, blinks)?)
+                .ok_or(BlinkError::Count(stone))
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+
+    // [rules]
+    // <the rule set>
+    // [counts]
+    // stone blinks count
+    fn save(&self, file_path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut entries: Vec<_> = self.counts.iter().collect();
+        entries.sort_unstable();
+
+        let mut text = format!("[rules]\n{}[counts]\n", self.rules);
+        for ((stone, blinks), count) in entries {
+            text.push_str(&format!("{} {} {}\n", stone, blinks, count));
+        }
+        std::fs::write(file_path, text)
+    }
+
+    // loads a saved cache, refusing one that was filled under other rules
+    fn load(file_path: impl AsRef<Path>, rules: RuleSet) -> Result<Self, CacheError> {
+        let text = std::fs::read_to_string(file_path)?;
+        let (header, body) = text
+            .strip_prefix("[rules]\n")
+            .and_then(|text| text.split_once("[counts]\n"))
+            .ok_or(CacheError::Parse {
+                line: 1,
+                message: "expected [rules] and [counts] sections".to_string(),
+            })?;
+        let saved_rules = RuleSet::parse(header).map_err(|err| CacheError::Parse {
+            line: err.line + 1,
+            message: err.message,
+        })?;
+        if saved_rules != rules {
+            return Err(CacheError::RulesMismatch);
+        }
+
+        let mut cache = Self::new(rules);
+        let offset = header.lines().count() + 2;
+        for (i, line) in body.lines().enumerate() {
+            let error = |message: String| CacheError::Parse {
+                line: offset + i + 1,
+                message,
+            };
+            let mut words = line.split_whitespace();
+            let stone = parse_number(words.next()).map_err(error)?;
+            let blinks = parse_number(words.next()).map_err(error)?;
+            let count = parse_number(words.next()).map_err(error)?;
+            cache.counts.insert((stone, blinks), count);
+        }
+        Ok(cache)
+    }
+}
+
 fn part01() {
     let input = get_input("input.txt");
     let mut stones = parse_input(&input);
+    let rules = RuleSet::standard();
 
     for _ in 0..25 {
-        stones = blink_stones(&stones);
+        stones = blink_stones(&rules, &stones).unwrap_or_else(|err| panic!("{}", err));
     }
 
     println!("{}", stones.len());
@@ -78,19 +1100,313 @@ fn part01() {
 fn part02() {
     let input = get_input("input.txt");
     let stones = parse_input(&input);
-    let mut map: HashMap<u64, u64> = stones.iter().fold(HashMap::new(), |mut map, &stone| {
+    let rules = RuleSet::standard();
+
+    let mut map: HashMap<Stone, Count> = stones.iter().fold(HashMap::new(), |mut map, &stone| {
         *map.entry(stone).or_insert(0) += 1;
         map
     });
     for _ in 0..75 {
-        map = blink_stones_batched(&map)
+        map = blink_stones_batched(&rules, &map).unwrap_or_else(|err| panic!("{}", err));
     }
 
-    let total_stones = map.iter().fold(0, |acc, (_, &val)| acc + val);
+    let total_stones = map
+        .values()
+        .try_fold(0 as Count, |acc, &count| acc.checked_add(count))
+        .expect("total number of stones overflows");
     println!("{}", total_stones);
 }
 
+fn variant() {
+    let input = get_input("input.txt");
+    let stones = parse_input(&input);
+    let rules = RuleSet::load("variant.txt").unwrap_or_else(|err| panic!("variant.txt: {}", err));
+
+    let mut map: HashMap<Stone, Count> = stones.iter().fold(HashMap::new(), |mut map, &stone| {
+        *map.entry(stone).or_insert(0) += 1;
+        map
+    });
+    for _ in 0..25 {
+        map = blink_stones_batched(&rules, &map).unwrap_or_else(|err| panic!("{}", err));
+    }
+
+    let total_stones = map
+        .values()
+        .try_fold(0 as Count, |acc, &count| acc.checked_add(count))
+        .expect("total number of stones overflows");
+    println!("variant: {}", total_stones);
+}
+
+// the cache is only read from and written to disk with `--cache`, as it grows
+// to a few megabytes
+fn memoised(persist: bool) {
+    let input = get_input("input.txt");
+    let stones = parse_input(&input);
+    let cache_path = "stones.cache";
+
+    let mut cache = StoneCache::new(RuleSet::standard());
+    if persist {
+        match StoneCache::load(cache_path, RuleSet::standard()) {
+            Ok(loaded) => cache = loaded,
+            Err(CacheError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => println!("{}: {}, starting afresh", cache_path, err),
+        }
+        println!("cache: {} entries loaded", cache.size());
+    }
+
+    for blinks in [25, 75] {
+        match cache.count_stones(&stones, blinks) {
+            Ok(count) => println!("{} blinks: {}", blinks, count),
+            Err(err) => println!("{} blinks: {}", blinks, err),
+        }
+    }
+    println!(
+        "cache: {} entries, {:.1}% hit rate",
+        cache.size(),
+        cache.hit_rate() * 100.0
+    );
+
+    if persist {
+        cache.save(cache_path).unwrap();
+    }
+}
+
+fn population() {
+    let input = get_input("input.txt");
+    let stones = parse_input(&input);
+    let population = analyse_population(&RuleSet::standard(), &stones, 75)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    println!("blink distinct total digits");
+    for stats in population.stats.iter().step_by(15) {
+        let histogram: Vec<String> = stats
+            .digit_histogram
+            .iter()
+            .map(|(digits, count)| format!("{}:{}", digits, count))
+            .collect();
+        println!(
+            "{:>5} {:>8} {:>15} {}",
+            stats.blink,
+            stats.distinct,
+            stats.total,
+            histogram.join(" ")
+        );
+    }
+    match population.closure {
+        Some(closure) => println!(
+            "closed after {} blinks with {} distinct values",
+            closure.blink,
+            closure.values.len()
+        ),
+        None => println!("no closure within 75 blinks"),
+    }
+}
+
+fn exponentiation() {
+    let input = get_input("input.txt");
+    let stones = parse_input(&input);
+    let matrix = TransitionMatrix::new(&RuleSet::standard(), &stones)
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let queries = [
+        (75, 1_000_000_007),
+        (1_000_000_000_000, 1_000_000_007),
+        // squarefree, the product of two primes
+        (1_000_000_000_000, 1_000_000_007 * 998_244_353),
+        (1_000_000_000_000, 1_000_000_000_000),
+    ];
+    for (blinks, modulus) in queries {
+        match matrix.count_mod(&stones, blinks, modulus) {
+            Ok(count) => println!("{} blinks mod {}: {}", blinks, modulus, count),
+            Err(err) => println!("{} blinks mod {}: {}", blinks, modulus, err),
+        }
+    }
+}
+
 fn main() {
     part01();
     part02();
+    variant();
+    memoised(std::env::args().any(|arg| arg == "--cache"));
+    population();
+    exponentiation();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [Stone; 2] = [125, 17];
+    const PRIME: u64 = 1_000_000_007;
+    // the largest prime below 2^64
+    const LARGE_PRIME: u64 = 18446744073709551557;
+
+    // the exact totals after 0..=blinks blinks
+    fn batched_totals(rules: &RuleSet, stones: &[Stone], blinks: usize) -> Vec<Count> {
+        let mut map: HashMap<Stone, Count> = HashMap::new();
+        for &stone in stones {
+            *map.entry(stone).or_insert(0) += 1;
+        }
+        let mut totals = Vec::new();
+        for _ in 0..=blinks {
+            totals.push(map.values().sum());
+            map = blink_stones_batched(rules, &map).unwrap();
+        }
+        totals
+    }
+
+    #[test]
+    fn count_mod_matches_batched_blinks() {
+        let rules = RuleSet::standard();
+        let matrix = TransitionMatrix::new(&rules, &EXAMPLE).unwrap();
+        let totals = batched_totals(&rules, &EXAMPLE, 60);
+
+        for modulus in [PRIME, PRIME * 998_244_353, 1_000_000_000_000, 1 << 63] {
+            for (blinks, &total) in totals.iter().enumerate() {
+                let expected = count_to_u64(total % modulus as Count);
+                let blinks = blinks as u64;
+                assert_eq!(matrix.count_mod(&EXAMPLE, blinks, modulus), Ok(expected));
+                assert_eq!(
+                    matrix.count_by_matrix_power(&EXAMPLE, blinks, modulus),
+                    expected
+                );
+                let primes = factorise(modulus);
+                if primes.windows(2).all(|pair| pair[0] != pair[1]) {
+                    assert_eq!(
+                        matrix.count_by_recurrence(&EXAMPLE, blinks, &primes),
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn count_mod_beyond_simulation() {
+        let rules = RuleSet::standard();
+        let matrix = TransitionMatrix::new(&rules, &EXAMPLE).unwrap();
+        let start = 2 * matrix.values.len() as u64;
+
+        for modulus in [PRIME, PRIME * 998_244_353, LARGE_PRIME] {
+            let mut counts = matrix.counts(&EXAMPLE, modulus);
+            for blinks in 0..start + 20 {
+                if blinks > start {
+                    assert_eq!(
+                        matrix.count_mod(&EXAMPLE, blinks, modulus),
+                        Ok(TransitionMatrix::total(&counts, modulus))
+                    );
+                }
+                counts = matrix.step(&counts, modulus);
+            }
+        }
+    }
+
+    #[test]
+    fn count_mod_rejects_unsupported_moduli() {
+        let input = get_input("input.txt");
+        let stones = parse_input(&input);
+        let matrix = TransitionMatrix::new(&RuleSet::standard(), &stones).unwrap();
+
+        assert_eq!(matrix.count_mod(&stones, 10, 0), Err(ModulusError::Zero));
+        assert!(matches!(
+            matrix.count_mod(&stones, 1_000_000_000_000, 1_000_000_000_000),
+            Err(ModulusError::RepeatedFactor { prime: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn factorise_finds_prime_factors() {
+        assert_eq!(factorise(1), Vec::<u64>::new());
+        assert_eq!(
+            factorise(1_000_000_000_000),
+            [vec![2; 12], vec![5; 12]].concat()
+        );
+        assert_eq!(factorise(PRIME * 998_244_353), vec![998_244_353, PRIME]);
+        assert_eq!(factorise(LARGE_PRIME), vec![LARGE_PRIME]);
+        assert!(!is_prime(4_294_967_297));
+    }
+
+    fn fibonacci(n: usize, modulus: u64) -> Vec<u64> {
+        let mut sequence = vec![0, 1 % modulus];
+        while sequence.len() < n {
+            let next = add_mod(
+                sequence[sequence.len() - 1],
+                sequence[sequence.len() - 2],
+                modulus,
+            );
+            sequence.push(next);
+        }
+        sequence
+    }
+
+    #[test]
+    fn berlekamp_massey_recovers_fibonacci() {
+        for modulus in [7, PRIME, LARGE_PRIME] {
+            let sequence = fibonacci(20, modulus);
+            assert_eq!(berlekamp_massey(&sequence, modulus), vec![1, 1]);
+        }
+    }
+
+    #[test]
+    fn power_of_x_jumps_ahead() {
+        for modulus in [7, PRIME, LARGE_PRIME] {
+            let sequence = fibonacci(300, modulus);
+            for n in [0, 1, 2, 17, 299] {
+                let coefficients = power_of_x(n as u64, &[1, 1], modulus);
+                let value = coefficients.iter().zip(&sequence).fold(0, |acc, (&c, &s)| {
+                    add_mod(acc, mul_mod(c, s, modulus), modulus)
+                });
+                assert_eq!(value, sequence[n], "F({}) mod {}", n, modulus);
+            }
+        }
+    }
+
+    #[test]
+    fn mod_matrix_mul_reduces_lazily() {
+        let size = 24;
+        // from one lazy term per reduction up to far more terms than the matrix is wide
+        for modulus in [LARGE_PRIME, 1 << 32, (1 << 32) + 15, 10] {
+            let mut a = ModMatrix::zero(size, modulus);
+            let mut b = ModMatrix::zero(size, modulus);
+            for (i, entry) in a.entries.iter_mut().enumerate() {
+                *entry = modulus - 1 - (i as u64 % 3).min(modulus - 1);
+            }
+            for (i, entry) in b.entries.iter_mut().enumerate() {
+                *entry = (i as u64 * 7919) % modulus;
+            }
+
+            let product = a.mul(&b);
+            for i in 0..size {
+                for j in 0..size {
+                    let expected = (0..size).fold(0, |acc, k| {
+                        let term =
+                            mul_mod(a.entries[i * size + k], b.entries[k * size + j], modulus);
+                        add_mod(acc, term, modulus)
+                    });
+                    assert_eq!(product.entries[i * size + j], expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let path = std::env::temp_dir().join(format!("day11-cache-{}", std::process::id()));
+        let mut cache = StoneCache::new(RuleSet::standard());
+        let count = cache.count_stones(&EXAMPLE, 25).unwrap();
+        assert_eq!(count, 55312);
+        cache.save(&path).unwrap();
+
+        let mut loaded = StoneCache::load(&path, RuleSet::standard()).unwrap();
+        assert_eq!(loaded.counts, cache.counts);
+        assert_eq!(loaded.count_stones(&EXAMPLE, 25).unwrap(), count);
+        assert_eq!(loaded.misses, 0);
+
+        let variant = RuleSet::load("variant.txt").unwrap();
+        assert!(matches!(
+            StoneCache::load(&path, variant),
+            Err(CacheError::RulesMismatch)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
 }
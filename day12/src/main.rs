@@ -1,10 +1,8 @@
 use core::fmt;
 use std::{
-    cmp::{max, min},
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     hash::Hash,
     ops::Deref,
-    time::Duration,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -84,10 +82,79 @@ impl fmt::Display for Map {
 }
 
 struct Region {
+    // index of the region in `find_regions` output and in its `RegionLabels`
+    label: usize,
     plant_id: PlantId,
     locations: Vec<IVec2>,
 }
 
+// which neighbouring cells of the same plant belong to the same region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    Four,
+    Eight,
+}
+
+// region label of every cell of the map, stored row-major
+#[derive(Debug, Clone)]
+struct RegionLabels {
+    width: usize,
+    height: usize,
+    labels: Vec<u32>,
+    connectivity: Connectivity,
+}
+
+impl RegionLabels {
+    fn get(&self, pos: IVec2) -> Option<usize> {
+        if pos.x >= 0
+            && pos.y >= 0
+            && (pos.x as usize) < self.width
+            && (pos.y as usize) < self.height
+        {
+            Some(self.labels[pos.y as usize * self.width + pos.x as usize] as usize)
+        } else {
+            None
+        }
+    }
+}
+
+// disjoint sets over the cells of the map, with path halving and union by size
+struct UnionFind {
+    parent: Vec<u32>,
+    size: Vec<u32>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len as u32).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] as usize != i {
+            let grandparent = self.parent[self.parent[i] as usize];
+            self.parent[i] = grandparent;
+            i = grandparent as usize;
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let mut a = self.find(a);
+        let mut b = self.find(b);
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a as u32;
+        self.size[a] += self.size[b];
+    }
+}
+
 fn get_input(file_path: &str) -> String {
     std::fs::read_to_string(file_path).unwrap()
 }
@@ -108,23 +175,6 @@ fn get_neighbors(cell: IVec2) -> Vec<IVec2> {
     ]
 }
 
-fn get_neighbors_checked(cell: IVec2, map: &Map) -> Vec<IVec2> {
-    let mut neighbors = Vec::new();
-    if cell.x > 0 {
-        neighbors.push(IVec2::new(cell.x - 1, cell.y));
-    }
-    if cell.x < map[0].len() as i32 - 1 {
-        neighbors.push(IVec2::new(cell.x + 1, cell.y));
-    }
-    if cell.y > 0 {
-        neighbors.push(IVec2::new(cell.x, cell.y - 1));
-    }
-    if cell.y < map.len() as i32 - 1 {
-        neighbors.push(IVec2::new(cell.x, cell.y + 1));
-    }
-    neighbors
-}
-
 fn get_diagonal_neighbors(cell: IVec2) -> Vec<IVec2> {
     vec![
         IVec2::new(cell.x - 1, cell.y - 1),
@@ -134,66 +184,74 @@ fn get_diagonal_neighbors(cell: IVec2) -> Vec<IVec2> {
     ]
 }
 
-fn find_regions(map: &Map) -> Vec<Region> {
-    let mut checked_cells: HashSet<IVec2> = HashSet::new();
-    let mut regions = Vec::new();
-    for y in 0..map.len() {
-        for x in 0..map[y].len() {
-            if checked_cells.contains(&IVec2::new(x as i32, y as i32)) {
-                continue;
-            }
-
-            checked_cells.insert(IVec2::new(x as i32, y as i32));
-
-            let mut region = Region {
-                plant_id: map[y][x],
-                locations: Vec::new(),
-            };
-
-            let mut queue = VecDeque::new();
-            let mut visited = HashSet::new();
+// labels every cell with its region in two linear sweeps: the first joins each
+// cell with its left and upper neighbours (plus the upper diagonals with
+// 8-connectivity) when they grow the same plant, the second numbers the
+// resulting sets in row-major order of their first cell
+fn find_regions(map: &Map, connectivity: Connectivity) -> (RegionLabels, Vec<Region>) {
+    let height = map.len();
+    let width = map.first().map_or(0, |row| row.len());
 
-            queue.push_back(IVec2::new(x as i32, y as i32));
-            loop {
-                if queue.is_empty() {
-                    break;
+    let mut sets = UnionFind::new(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let plant = Some(map[y][x]);
+            if map.get(IVec2::new(x as i32 - 1, y as i32)) == plant {
+                sets.union(i, i - 1);
+            }
+            if map.get(IVec2::new(x as i32, y as i32 - 1)) == plant {
+                sets.union(i, i - width);
+            }
+            if connectivity == Connectivity::Eight {
+                if map.get(IVec2::new(x as i32 - 1, y as i32 - 1)) == plant {
+                    sets.union(i, i - width - 1);
                 }
-
-                let cell = queue.pop_front().unwrap();
-                if visited.contains(&cell) {
-                    continue;
+                if map.get(IVec2::new(x as i32 + 1, y as i32 - 1)) == plant {
+                    sets.union(i, i - width + 1);
                 }
-                visited.insert(cell);
-
-                if map[cell.y as usize][cell.x as usize] != region.plant_id {
-                    continue;
-                }
-
-                region.locations.push(cell);
-                checked_cells.insert(cell);
+            }
+        }
+    }
 
-                queue.extend(get_neighbors_checked(cell, map));
+    let mut root_labels = vec![u32::MAX; width * height];
+    let mut labels = vec![0; width * height];
+    let mut regions: Vec<Region> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let root = sets.find(y * width + x);
+            if root_labels[root] == u32::MAX {
+                root_labels[root] = regions.len() as u32;
+                regions.push(Region {
+                    label: regions.len(),
+                    plant_id: map[y][x],
+                    locations: Vec::with_capacity(sets.size[root] as usize),
+                });
             }
 
-            regions.push(region);
+            let label = root_labels[root];
+            labels[y * width + x] = label;
+            regions[label as usize]
+                .locations
+                .push(IVec2::new(x as i32, y as i32));
         }
     }
 
-    regions
+    let labels = RegionLabels {
+        width,
+        height,
+        labels,
+        connectivity,
+    };
+    (labels, regions)
 }
 
-fn calc_perimeter(region: &Region, map: &Map) -> i32 {
+fn calc_perimeter(region: &Region, labels: &RegionLabels) -> i32 {
     let mut perimeter = 0;
     for &cell in &region.locations {
         for neighbor in get_neighbors(cell) {
-            // if out of bounds, its perimeter
-            // or if the neighbor is not the same as the region
-            if neighbor.y < 0
-                || neighbor.y >= map.len() as i32
-                || neighbor.x < 0
-                || neighbor.x >= map[0].len() as i32
-                || region.plant_id != map[neighbor.y as usize][neighbor.x as usize]
-            {
+            // if out of bounds, or the neighbor belongs to another region, its perimeter
+            if labels.get(neighbor) != Some(region.label) {
                 perimeter += 1;
             }
         }
@@ -201,28 +259,20 @@ fn calc_perimeter(region: &Region, map: &Map) -> i32 {
     perimeter
 }
 
-fn count_sides(region: &Region, map: &Map) -> i32 {
+fn count_sides(region: &Region, labels: &RegionLabels) -> i32 {
     // number of sides is equal to number of corners
     // a corner is a cell where there is 1 or 3 neighboring cells
     let mut sides = 0;
-    let mut corners = vec![vec![0; map[0].len()]; map.len()];
+    let in_region = |pos: IVec2| labels.get(pos) == Some(region.label);
     for &cell in &region.locations {
         for diagonal in get_diagonal_neighbors(cell) {
-            let diagonal_is_same_region = map.is_within_bounds(diagonal)
-                && map[diagonal.y as usize][diagonal.x as usize] == region.plant_id;
-
-            let n1 = IVec2::new(cell.x, diagonal.y);
-            let n2 = IVec2::new(diagonal.x, cell.y);
-
-            let n1_same_region =
-                map.is_within_bounds(n1) && map[n1.y as usize][n1.x as usize] == region.plant_id;
-            let n2_same_region =
-                map.is_within_bounds(n2) && map[n2.y as usize][n2.x as usize] == region.plant_id;
+            let diagonal_is_same_region = in_region(diagonal);
+            let n1_same_region = in_region(IVec2::new(cell.x, diagonal.y));
+            let n2_same_region = in_region(IVec2::new(diagonal.x, cell.y));
 
             if (n1_same_region && n2_same_region && !diagonal_is_same_region)
                 || (!n1_same_region && !n2_same_region)
             {
-                corners[cell.y as usize][cell.x as usize] += 1;
                 sides += 1;
             }
         }
@@ -234,32 +284,854 @@ fn calc_area(region: &Region) -> i32 {
     region.locations.len() as i32
 }
 
-fn calc_price(region: &Region, map: &Map) -> i32 {
-    let perimeter = calc_perimeter(region, map);
-    let area = calc_area(region);
-    perimeter * area
+// a closed rectilinear ring through the corners of its boundary, in map
+// coordinates where the vertex (x, y) is the top-left corner of cell (x, y)
+#[derive(Debug, Clone)]
+struct Polygon {
+    vertices: Vec<IVec2>,
 }
 
-fn calc_discount_price(region: &Region, map: &Map) -> i32 {
-    let sides = count_sides(region, map);
-    let area = calc_area(region);
-    println!(
-        "A region of {} plants with price of {} * {} = {}",
-        region.plant_id.0,
-        area,
-        sides,
-        area * sides
+impl Polygon {
+    // twice the signed area, positive for rings that run clockwise on screen
+    fn signed_area2(&self) -> i64 {
+        let n = self.vertices.len();
+        (0..n)
+            .map(|i| {
+                let a = self.vertices[i];
+                let b = self.vertices[(i + 1) % n];
+                a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64
+            })
+            .sum()
+    }
+
+    fn perimeter(&self) -> i32 {
+        let n = self.vertices.len();
+        (0..n)
+            .map(|i| {
+                let d = self.vertices[(i + 1) % n] - self.vertices[i];
+                d.x.abs() + d.y.abs()
+            })
+            .sum()
+    }
+
+    fn sides(&self) -> i32 {
+        self.vertices.len() as i32
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RegionOutline {
+    outer: Polygon,
+    holes: Vec<Polygon>,
+}
+
+impl RegionOutline {
+    fn rings(&self) -> impl Iterator<Item = &Polygon> {
+        std::iter::once(&self.outer).chain(&self.holes)
+    }
+
+    fn perimeter(&self) -> i32 {
+        self.rings().map(Polygon::perimeter).sum()
+    }
+
+    fn sides(&self) -> i32 {
+        self.rings().map(Polygon::sides).sum()
+    }
+}
+
+fn turn_right(dir: IVec2) -> IVec2 {
+    IVec2::new(-dir.y, dir.x)
+}
+
+fn turn_left(dir: IVec2) -> IVec2 {
+    IVec2::new(dir.y, -dir.x)
+}
+
+// traces the fences of a region as rings of unit edges that keep the region on
+// their right, so the outer ring runs clockwise and holes counter-clockwise.
+// where two diagonal cells of the region touch, the walk turns right with
+// 4-connectivity and left with 8-connectivity, so it follows the cells that are
+// actually connected. either way there is one corner per touching cell, the
+// same as `count_sides`
+fn trace_outline(region: &Region, labels: &RegionLabels) -> RegionOutline {
+    let in_region = |pos: IVec2| labels.get(pos) == Some(region.label);
+
+    // outgoing fence directions at every boundary vertex
+    let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+    for &cell in &region.locations {
+        let sides = [
+            (IVec2::new(0, -1), cell, IVec2::new(1, 0)),
+            (IVec2::new(1, 0), cell + IVec2::new(1, 0), IVec2::new(0, 1)),
+            (IVec2::new(0, 1), cell + IVec2::new(1, 1), IVec2::new(-1, 0)),
+            (
+                IVec2::new(-1, 0),
+                cell + IVec2::new(0, 1),
+                IVec2::new(0, -1),
+            ),
+        ];
+        for (neighbor_dir, start, dir) in sides {
+            if !in_region(cell + neighbor_dir) {
+                edges.entry(start).or_default().push(dir);
+            }
+        }
+    }
+
+    let mut visited: HashSet<(IVec2, IVec2)> = HashSet::new();
+    let mut outer = None;
+    let mut holes = Vec::new();
+
+    // walk in row-major order of the start vertex so the output is deterministic
+    let mut starts: Vec<(IVec2, IVec2)> = edges
+        .iter()
+        .flat_map(|(&start, dirs)| dirs.iter().map(move |&dir| (start, dir)))
+        .collect();
+    starts.sort_by_key(|(start, dir)| (start.y, start.x, dir.y, dir.x));
+
+    for first in starts {
+        if visited.contains(&first) {
+            continue;
+        }
+
+        let mut vertices = Vec::new();
+        let (mut pos, mut dir) = first;
+        loop {
+            visited.insert((pos, dir));
+            pos = pos + dir;
+
+            let outgoing = &edges[&pos];
+            let turns = match labels.connectivity {
+                Connectivity::Four => [turn_right(dir), dir, turn_left(dir)],
+                Connectivity::Eight => [turn_left(dir), dir, turn_right(dir)],
+            };
+            let next = turns.into_iter().find(|d| outgoing.contains(d)).unwrap();
+            if next != dir {
+                vertices.push(pos);
+            }
+            dir = next;
+
+            if (pos, dir) == first {
+                break;
+            }
+        }
+
+        let ring = Polygon { vertices };
+        if ring.signed_area2() > 0 && outer.is_none() {
+            outer = Some(ring);
+        } else {
+            holes.push(ring);
+        }
+    }
+
+    RegionOutline {
+        outer: outer.unwrap(),
+        holes,
+    }
+}
+
+// a hole in a region: cells of other regions that cannot reach the edge of
+// the map without crossing the region
+#[derive(Debug, Clone)]
+struct Hole {
+    cells: usize,
+    regions: Vec<usize>,
+}
+
+// the cell on the left of the fence that starts at `vertex` and runs along `dir`
+fn cell_left_of(vertex: IVec2, dir: IVec2) -> IVec2 {
+    let left = turn_left(dir);
+    IVec2::new(
+        (2 * vertex.x + dir.x + left.x - 1).div_euclid(2),
+        (2 * vertex.y + dir.y + left.y - 1).div_euclid(2),
+    )
+}
+
+// fences keep the region on their right, so every hole ring has the hole on its
+// left. from there the hole is filled with the opposite connectivity of the
+// regions: cells that only touch diagonally are not separated by a 4-connected
+// region, but are by an 8-connected one
+fn find_holes(region: &Region, outline: &RegionOutline, labels: &RegionLabels) -> Vec<Hole> {
+    outline
+        .holes
+        .iter()
+        .map(|ring| {
+            let dir = ring.vertices[1] - ring.vertices[0];
+            let dir = IVec2::new(dir.x.signum(), dir.y.signum());
+            let seed = cell_left_of(ring.vertices[0], dir);
+
+            let mut visited = HashSet::from([seed]);
+            let mut stack = vec![seed];
+            let mut regions = HashSet::new();
+            while let Some(cell) = stack.pop() {
+                regions.insert(labels.get(cell).unwrap());
+                let mut neighbors = get_neighbors(cell);
+                if labels.connectivity == Connectivity::Four {
+                    neighbors.extend(get_diagonal_neighbors(cell));
+                }
+                for neighbor in neighbors {
+                    let label = labels.get(neighbor).unwrap();
+                    if label != region.label && visited.insert(neighbor) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            let mut regions: Vec<usize> = regions.into_iter().collect();
+            regions.sort_unstable();
+            Hole {
+                cells: visited.len(),
+                regions,
+            }
+        })
+        .collect()
+}
+
+// which regions sit inside the holes of which, indexed by region label
+#[derive(Debug, Clone)]
+struct Nesting {
+    holes: Vec<Vec<Hole>>,
+    // the innermost region enclosing each region, `None` for the outermost ones
+    parent: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl Nesting {
+    fn depth(&self, label: usize) -> usize {
+        let mut depth = 0;
+        let mut current = label;
+        while let Some(parent) = self.parent[current] {
+            depth += 1;
+            current = parent;
+        }
+        depth
+    }
+
+    fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parent.len()).filter(|&label| self.parent[label].is_none())
+    }
+}
+
+fn build_nesting(regions: &[Region], labels: &RegionLabels) -> Nesting {
+    let holes: Vec<Vec<Hole>> = regions
+        .iter()
+        .map(|region| find_holes(region, &trace_outline(region, labels), labels))
+        .collect();
+
+    // the innermost enclosing region is the one with the smallest hole around it
+    let mut parent: Vec<Option<usize>> = vec![None; regions.len()];
+    let mut parent_hole_size = vec![usize::MAX; regions.len()];
+    for (label, region_holes) in holes.iter().enumerate() {
+        for hole in region_holes {
+            for &inner in &hole.regions {
+                if hole.cells < parent_hole_size[inner] {
+                    parent_hole_size[inner] = hole.cells;
+                    parent[inner] = Some(label);
+                }
+            }
+        }
+    }
+
+    let mut children = vec![Vec::new(); regions.len()];
+    for (label, &p) in parent.iter().enumerate() {
+        if let Some(p) = p {
+            children[p].push(label);
+        }
+    }
+
+    Nesting {
+        holes,
+        parent,
+        children,
+    }
+}
+
+// euler characteristic of all regions growing one plant: each region is a
+// connected component and each of its holes removes one from the count
+#[derive(Debug, Clone, Copy)]
+struct PlantTopology {
+    plant_id: PlantId,
+    components: usize,
+    holes: usize,
+}
+
+impl PlantTopology {
+    fn euler_characteristic(&self) -> i64 {
+        self.components as i64 - self.holes as i64
+    }
+}
+
+fn plant_topology(regions: &[Region], nesting: &Nesting) -> Vec<PlantTopology> {
+    let mut topology: Vec<PlantTopology> = Vec::new();
+    for region in regions {
+        let holes = nesting.holes[region.label].len();
+        match topology.iter_mut().find(|t| t.plant_id == region.plant_id) {
+            Some(t) => {
+                t.components += 1;
+                t.holes += holes;
+            }
+            None => topology.push(PlantTopology {
+                plant_id: region.plant_id,
+                components: 1,
+                holes,
+            }),
+        }
+    }
+    topology.sort_by_key(|t| t.plant_id.0);
+    topology
+}
+
+// regions that share a fence, keyed by (smaller label, larger label) with the
+// number of unit edges they share
+#[derive(Debug, Clone, Default)]
+struct AdjacencyGraph {
+    edges: BTreeMap<(usize, usize), usize>,
+}
+
+impl AdjacencyGraph {
+    fn neighbors(&self, label: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.edges.iter().filter_map(move |(&(a, b), &shared)| {
+            if a == label {
+                Some((b, shared))
+            } else if b == label {
+                Some((a, shared))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn to_dot(&self, regions: &[Region]) -> String {
+        let mut dot = String::from("graph regions {\n");
+        for region in regions {
+            dot += &format!(
+                "  {} [label=\"{} ({})\"];\n",
+                region.label,
+                region.plant_id.0,
+                calc_area(region)
+            );
+        }
+        for (&(a, b), &shared) in &self.edges {
+            dot += &format!("  {} -- {} [label=\"{}\"];\n", a, b, shared);
+        }
+        dot + "}\n"
+    }
+
+    fn to_json(&self, regions: &[Region]) -> String {
+        let nodes = regions
+            .iter()
+            .map(|region| {
+                format!(
+                    "{{\"id\":{},\"plant\":\"{}\",\"area\":{}}}",
+                    region.label,
+                    region.plant_id.0.escape_default(),
+                    calc_area(region)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        let edges = self
+            .edges
+            .iter()
+            .map(|(&(a, b), &shared)| {
+                format!(
+                    "{{\"source\":{},\"target\":{},\"shared_edges\":{}}}",
+                    a, b, shared
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+        format!("{{\"nodes\":[{}],\"edges\":[{}]}}\n", nodes, edges)
+    }
+}
+
+// every pair of cells is compared once, through the right and lower neighbour
+fn build_adjacency(labels: &RegionLabels) -> AdjacencyGraph {
+    let mut graph = AdjacencyGraph::default();
+    for y in 0..labels.height as i32 {
+        for x in 0..labels.width as i32 {
+            let cell = IVec2::new(x, y);
+            let label = labels.get(cell).unwrap();
+            for neighbor in [IVec2::new(x + 1, y), IVec2::new(x, y + 1)] {
+                match labels.get(neighbor) {
+                    Some(other) if other != label => {
+                        *graph
+                            .edges
+                            .entry((label.min(other), label.max(other)))
+                            .or_insert(0) += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    graph
+}
+
+// the other plants growing right next to each plant
+fn plant_borders(graph: &AdjacencyGraph, regions: &[Region]) -> BTreeMap<char, BTreeSet<char>> {
+    let mut borders: BTreeMap<char, BTreeSet<char>> = BTreeMap::new();
+    for &(a, b) in graph.edges.keys() {
+        let (pa, pb) = (regions[a].plant_id.0, regions[b].plant_id.0);
+        if pa != pb {
+            borders.entry(pa).or_default().insert(pb);
+            borders.entry(pb).or_default().insert(pa);
+        }
+    }
+    borders
+}
+
+trait PricingStrategy {
+    fn name(&self) -> &str;
+
+    fn price(&self, region: &Region, labels: &RegionLabels) -> i64;
+
+    // how the price came about, for the itemised invoice
+    fn explain(&self, region: &Region, labels: &RegionLabels) -> String {
+        self.price(region, labels).to_string()
+    }
+}
+
+// area * perimeter
+struct PerimeterPricing;
+
+impl PricingStrategy for PerimeterPricing {
+    fn name(&self) -> &str {
+        "perimeter"
+    }
+
+    fn price(&self, region: &Region, labels: &RegionLabels) -> i64 {
+        calc_area(region) as i64 * calc_perimeter(region, labels) as i64
+    }
+
+    fn explain(&self, region: &Region, labels: &RegionLabels) -> String {
+        let area = calc_area(region);
+        let perimeter = calc_perimeter(region, labels);
+        format!(
+            "{} * {} = {}",
+            area,
+            perimeter,
+            area as i64 * perimeter as i64
+        )
+    }
+}
+
+// area * number of sides, the bulk discount
+struct SidesPricing;
+
+impl PricingStrategy for SidesPricing {
+    fn name(&self) -> &str {
+        "sides"
+    }
+
+    fn price(&self, region: &Region, labels: &RegionLabels) -> i64 {
+        calc_area(region) as i64 * count_sides(region, labels) as i64
+    }
+
+    fn explain(&self, region: &Region, labels: &RegionLabels) -> String {
+        let area = calc_area(region);
+        let sides = count_sides(region, labels);
+        format!("{} * {} = {}", area, sides, area as i64 * sides as i64)
+    }
+}
+
+// fences the bounding rectangle of the region instead: its area * its perimeter
+struct BoundingBoxPricing;
+
+impl PricingStrategy for BoundingBoxPricing {
+    fn name(&self) -> &str {
+        "bounding box"
+    }
+
+    fn price(&self, region: &Region, _labels: &RegionLabels) -> i64 {
+        let (width, height) = bounding_box_size(region);
+        width * height * 2 * (width + height)
+    }
+
+    fn explain(&self, region: &Region, labels: &RegionLabels) -> String {
+        let (width, height) = bounding_box_size(region);
+        format!(
+            "{}x{} box: {} * {} = {}",
+            width,
+            height,
+            width * height,
+            2 * (width + height),
+            self.price(region, labels)
+        )
+    }
+}
+
+fn bounding_box_size(region: &Region) -> (i64, i64) {
+    let min_x = region.locations.iter().map(|p| p.x).min().unwrap();
+    let max_x = region.locations.iter().map(|p| p.x).max().unwrap();
+    let min_y = region.locations.iter().map(|p| p.y).min().unwrap();
+    let max_y = region.locations.iter().map(|p| p.y).max().unwrap();
+    ((max_x - min_x + 1) as i64, (max_y - min_y + 1) as i64)
+}
+
+// fences the convex hull of the region with straight runs: the hull area,
+// rounded up to whole cells, * the number of hull sides
+struct ConvexHullPricing;
+
+impl PricingStrategy for ConvexHullPricing {
+    fn name(&self) -> &str {
+        "convex hull"
+    }
+
+    fn price(&self, region: &Region, labels: &RegionLabels) -> i64 {
+        let hull = convex_hull(&trace_outline(region, labels).outer.vertices);
+        let area = (hull.signed_area2().abs() + 1) / 2;
+        area * hull.sides() as i64
+    }
+}
+
+// monotone chain over the corners of the outer ring, clockwise on screen
+fn convex_hull(points: &[IVec2]) -> Polygon {
+    let mut points = points.to_vec();
+    points.sort_by_key(|p| (p.x, p.y));
+    points.dedup();
+
+    let cross = |o: IVec2, a: IVec2, b: IVec2| {
+        let (oa, ob) = (a - o, b - o);
+        oa.x as i64 * ob.y as i64 - oa.y as i64 * ob.x as i64
+    };
+
+    let mut hull: Vec<IVec2> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let ordered: Box<dyn Iterator<Item = &IVec2>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &p in ordered {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+
+    Polygon { vertices: hull }
+}
+
+// prices every region, optionally printing an itemised invoice along the way
+fn calc_total_price(
+    regions: &[Region],
+    labels: &RegionLabels,
+    strategy: &dyn PricingStrategy,
+    itemise: bool,
+) -> i64 {
+    if itemise {
+        println!("invoice ({} pricing)", strategy.name());
+    }
+
+    let mut total = 0;
+    for region in regions {
+        if itemise {
+            println!(
+                "A region of {} plants with price of {}",
+                region.plant_id.0,
+                strategy.explain(region, labels)
+            );
+        }
+        total += strategy.price(region, labels);
+    }
+    total
+}
+
+// change in total price caused by a single edit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PriceDelta {
+    before: i64,
+    after: i64,
+}
+
+impl PriceDelta {
+    fn delta(&self) -> i64 {
+        self.after - self.before
+    }
+}
+
+// a map whose regions are kept up to date while cells are replanted.
+// an edit only touches the regions around the changed cell: the old region is
+// re-flooded to find out whether it split, and the neighbouring regions of the
+// new plant are merged into the largest one
+struct Garden {
+    map: Map,
+    labels: RegionLabels,
+    // indexed by label, regions that were merged away are left empty
+    regions: Vec<Region>,
+    free_labels: Vec<usize>,
+    strategy: Box<dyn PricingStrategy>,
+}
+
+impl Garden {
+    fn new(map: Map, connectivity: Connectivity, strategy: Box<dyn PricingStrategy>) -> Self {
+        let (labels, regions) = find_regions(&map, connectivity);
+        Self {
+            map,
+            labels,
+            regions,
+            free_labels: Vec::new(),
+            strategy,
+        }
+    }
+
+    fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().filter(|r| !r.locations.is_empty())
+    }
+
+    fn region_at(&self, pos: IVec2) -> Option<&Region> {
+        self.labels.get(pos).map(|label| &self.regions[label])
+    }
+
+    fn total_price(&self) -> i64 {
+        self.regions()
+            .map(|region| self.strategy.price(region, &self.labels))
+            .sum()
+    }
+
+    fn connected_neighbors(&self, cell: IVec2) -> Vec<IVec2> {
+        let mut neighbors = get_neighbors(cell);
+        if self.labels.connectivity == Connectivity::Eight {
+            neighbors.extend(get_diagonal_neighbors(cell));
+        }
+        neighbors.retain(|&n| self.map.is_within_bounds(n));
+        neighbors
+    }
+
+    // every region whose fences can change when `cell` changes
+    fn labels_around(&self, cell: IVec2) -> Vec<usize> {
+        let mut labels: Vec<usize> = std::iter::once(cell)
+            .chain(get_neighbors(cell))
+            .chain(get_diagonal_neighbors(cell))
+            .filter_map(|pos| self.labels.get(pos))
+            .collect();
+        labels.sort_unstable();
+        labels.dedup();
+        labels
+    }
+
+    fn price_of(&self, labels: &[usize]) -> i64 {
+        labels
+            .iter()
+            .map(|&label| &self.regions[label])
+            .filter(|region| !region.locations.is_empty())
+            .map(|region| self.strategy.price(region, &self.labels))
+            .sum()
+    }
+
+    fn set_label(&mut self, pos: IVec2, label: usize) {
+        self.labels.labels[pos.y as usize * self.labels.width + pos.x as usize] = label as u32;
+    }
+
+    fn new_region(&mut self, plant_id: PlantId, locations: Vec<IVec2>) -> usize {
+        let label = self.free_labels.pop().unwrap_or(self.regions.len());
+        for &pos in &locations {
+            self.set_label(pos, label);
+        }
+        let region = Region {
+            label,
+            plant_id,
+            locations,
+        };
+        if label == self.regions.len() {
+            self.regions.push(region);
+        } else {
+            self.regions[label] = region;
+        }
+        label
+    }
+
+    fn free_region(&mut self, label: usize) -> Vec<IVec2> {
+        self.free_labels.push(label);
+        std::mem::take(&mut self.regions[label].locations)
+    }
+
+    fn set(&mut self, pos: IVec2, plant_id: PlantId) -> PriceDelta {
+        let old_label = self.labels.get(pos).expect("position outside the garden");
+        let affected = self.labels_around(pos);
+        let before = self.price_of(&affected);
+        if self.map[pos.y as usize][pos.x as usize] == plant_id {
+            return PriceDelta {
+                before,
+                after: before,
+            };
+        }
+
+        self.map.0[pos.y as usize][pos.x as usize] = plant_id;
+        self.remove_cell(old_label, pos);
+        self.add_cell(pos, plant_id);
+
+        let after = self.price_of(&self.labels_around(pos));
+        PriceDelta { before, after }
+    }
+
+    // takes `cell` out of its region and splits whatever is left into the
+    // pieces that are still connected, the largest piece keeps the label
+    fn remove_cell(&mut self, label: usize, cell: IVec2) {
+        let region = &mut self.regions[label];
+        region.locations.retain(|&pos| pos != cell);
+        if region.locations.is_empty() {
+            self.free_labels.push(label);
+            return;
+        }
+        let remaining = region.locations.len();
+        let plant_id = region.plant_id;
+
+        let mut visited: HashSet<IVec2> = HashSet::from([cell]);
+        let mut pieces: Vec<Vec<IVec2>> = Vec::new();
+        for seed in self.connected_neighbors(cell) {
+            if self.labels.get(seed) != Some(label) || visited.contains(&seed) {
+                continue;
+            }
+
+            visited.insert(seed);
+            let mut piece = vec![seed];
+            let mut next = 0;
+            while next < piece.len() {
+                let current = piece[next];
+                next += 1;
+                for neighbor in self.connected_neighbors(current) {
+                    if self.labels.get(neighbor) == Some(label) && visited.insert(neighbor) {
+                        piece.push(neighbor);
+                    }
+                }
+            }
+
+            // everything was reached from the first neighbour, nothing split
+            if pieces.is_empty() && piece.len() == remaining {
+                return;
+            }
+            pieces.push(piece);
+        }
+
+        pieces.sort_by_key(|piece| std::cmp::Reverse(piece.len()));
+        for piece in pieces.drain(1..) {
+            self.new_region(plant_id, piece);
+        }
+        let mut largest = pieces.pop().unwrap();
+        largest.sort_by_key(|pos| (pos.y, pos.x));
+        self.regions[label].locations = largest;
+    }
+
+    // puts `cell` into the region of its new plant, merging all neighbouring
+    // regions of that plant into the largest of them
+    fn add_cell(&mut self, cell: IVec2, plant_id: PlantId) {
+        let mut labels: Vec<usize> = self
+            .connected_neighbors(cell)
+            .into_iter()
+            .filter(|&n| self.map[n.y as usize][n.x as usize] == plant_id)
+            .filter_map(|n| self.labels.get(n))
+            .collect();
+        labels.sort_unstable();
+        labels.dedup();
+
+        let Some(&target) = labels
+            .iter()
+            .max_by_key(|&&label| self.regions[label].locations.len())
+        else {
+            self.new_region(plant_id, vec![cell]);
+            return;
+        };
+
+        for label in labels.into_iter().filter(|&label| label != target) {
+            let moved = self.free_region(label);
+            for &pos in &moved {
+                self.set_label(pos, target);
+            }
+            self.regions[target].locations.extend(moved);
+        }
+        self.set_label(cell, target);
+        self.regions[target].locations.push(cell);
+    }
+}
+
+fn plant_hue(plant_id: PlantId) -> u32 {
+    // spread neighbouring letters around the colour wheel
+    (*plant_id as u32 * 137) % 360
+}
+
+const SVG_CELL_SIZE: i32 = 12;
+
+fn ring_path(ring: &Polygon) -> String {
+    let mut path = String::new();
+    for (i, v) in ring.vertices.iter().enumerate() {
+        let command = if i == 0 { 'M' } else { 'L' };
+        path += &format!(
+            "{}{} {} ",
+            command,
+            v.x * SVG_CELL_SIZE,
+            v.y * SVG_CELL_SIZE
+        );
+    }
+    path + "Z"
+}
+
+// draws every region with fences coloured by plant, labelled with its
+// sides/perimeter and with the full pricing in its tooltip
+fn render_svg(map: &Map, labels: &RegionLabels, regions: &[Region]) -> String {
+    let width = map.first().map_or(0, |row| row.len()) as i32 * SVG_CELL_SIZE;
+    let height = map.len() as i32 * SVG_CELL_SIZE;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"-2 -2 {} {}\">\n",
+        width + 4,
+        height + 4,
+        width + 4,
+        height + 4
     );
-    sides * area
+
+    for region in regions {
+        let outline = trace_outline(region, labels);
+        let hue = plant_hue(region.plant_id);
+        let area = calc_area(region);
+        let perimeter = outline.perimeter();
+        let sides = outline.sides();
+
+        let path = outline
+            .rings()
+            .map(ring_path)
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        svg += &format!(
+            "<g><title>{}: area {}, perimeter {}, sides {}, price {}, discount price {}</title>\n",
+            region.plant_id.0,
+            area,
+            perimeter,
+            sides,
+            area * perimeter,
+            area * sides
+        );
+        svg += &format!(
+            "<path d=\"{}\" fill=\"hsl({}, 60%, 85%)\" fill-rule=\"evenodd\" stroke=\"hsl({}, 70%, 35%)\" stroke-width=\"2\"/>\n",
+            path, hue, hue
+        );
+
+        let first = region.locations[0];
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"monospace\">{}/{}</text></g>\n",
+            first.x * SVG_CELL_SIZE + 2,
+            (first.y + 1) * SVG_CELL_SIZE - 2,
+            SVG_CELL_SIZE / 2,
+            sides,
+            perimeter
+        );
+    }
+
+    svg + "</svg>\n"
 }
 
 fn part01() {
     let input = get_input("input.txt");
     let map = parse_input(&input);
 
-    let regions = find_regions(&map);
+    let (labels, regions) = find_regions(&map, Connectivity::Four);
 
-    let total_price = regions.iter().map(|r| calc_price(r, &map)).sum::<i32>();
+    let total_price = calc_total_price(&regions, &labels, &PerimeterPricing, false);
     println!("total price {}", total_price);
 }
 
@@ -267,17 +1139,245 @@ fn part02() {
     let input = get_input("input.txt");
     let map = parse_input(&input);
 
-    let regions = find_regions(&map);
-
-    let total_price = regions
-        .iter()
-        .map(|r| calc_discount_price(r, &map))
-        .sum::<i32>();
+    let (labels, regions) = find_regions(&map, Connectivity::Four);
 
+    let total_price = calc_total_price(&regions, &labels, &SidesPricing, false);
     println!("total price {}", total_price);
 }
 
+fn render() {
+    let input = get_input("input.txt");
+    let map = parse_input(&input);
+
+    let (labels, regions) = find_regions(&map, Connectivity::Four);
+    std::fs::write("regions.svg", render_svg(&map, &labels, &regions)).unwrap();
+}
+
+fn topology() {
+    let input = get_input("input.txt");
+    let map = parse_input(&input);
+
+    let (labels, regions) = find_regions(&map, Connectivity::Four);
+    let nesting = build_nesting(&regions, &labels);
+
+    let max_depth = (0..regions.len())
+        .map(|label| nesting.depth(label))
+        .max()
+        .unwrap_or(0);
+    let widest = (0..regions.len()).max_by_key(|&label| nesting.children[label].len());
+    println!(
+        "{} regions, {} outermost, nested at most {} deep",
+        regions.len(),
+        nesting.roots().count(),
+        max_depth
+    );
+    if let Some(label) = widest {
+        println!(
+            "region {} ({}) directly encloses {} regions",
+            label,
+            regions[label].plant_id.0,
+            nesting.children[label].len()
+        );
+    }
+
+    for t in plant_topology(&regions, &nesting) {
+        println!(
+            "{}: {} components, {} holes, euler characteristic {}",
+            t.plant_id.0,
+            t.components,
+            t.holes,
+            t.euler_characteristic()
+        );
+    }
+}
+
+fn adjacency() {
+    let input = get_input("input.txt");
+    let map = parse_input(&input);
+
+    let (labels, regions) = find_regions(&map, Connectivity::Four);
+    let graph = build_adjacency(&labels);
+    std::fs::write("regions.dot", graph.to_dot(&regions)).unwrap();
+    std::fs::write("regions.json", graph.to_json(&regions)).unwrap();
+
+    let busiest = (0..regions.len()).max_by_key(|&label| graph.neighbors(label).count());
+    if let Some(label) = busiest {
+        println!(
+            "region {} ({}) borders {} other regions",
+            label,
+            regions[label].plant_id.0,
+            graph.neighbors(label).count()
+        );
+    }
+
+    let borders = plant_borders(&graph, &regions);
+    if let Some((plant, others)) = borders.iter().max_by_key(|(_, others)| others.len()) {
+        println!("{} borders the most other plants: {}", plant, others.len());
+    }
+}
+
+fn compare_pricing() {
+    let input = get_input("input.txt");
+    let map = parse_input(&input);
+
+    let strategies: [&dyn PricingStrategy; 4] = [
+        &PerimeterPricing,
+        &SidesPricing,
+        &BoundingBoxPricing,
+        &ConvexHullPricing,
+    ];
+    for connectivity in [Connectivity::Four, Connectivity::Eight] {
+        let (labels, regions) = find_regions(&map, connectivity);
+        for strategy in strategies {
+            println!(
+                "{:?}-connected, {} pricing: {}",
+                connectivity,
+                strategy.name(),
+                calc_total_price(&regions, &labels, strategy, false)
+            );
+        }
+    }
+}
+
+fn replant() {
+    let input = get_input("example.txt");
+    let map = parse_input(&input);
+
+    let mut garden = Garden::new(map, Connectivity::Four, Box::new(SidesPricing));
+    println!("garden price {}", garden.total_price());
+
+    // cut the B patches in half, then grow them back
+    let edits = [
+        (IVec2::new(3, 2), PlantId('A')),
+        (IVec2::new(2, 3), PlantId('A')),
+        (IVec2::new(3, 2), PlantId('B')),
+        (IVec2::new(2, 3), PlantId('B')),
+    ];
+    for (pos, plant_id) in edits {
+        let delta = garden.set(pos, plant_id);
+        let region = garden.region_at(pos).unwrap();
+        println!(
+            "set ({}, {}) to {}: price {:+}, now in a region of {} cells, garden price {}",
+            pos.x,
+            pos.y,
+            plant_id.0,
+            delta.delta(),
+            calc_area(region),
+            garden.total_price()
+        );
+    }
+}
+
 fn main() {
     part01();
     part02();
+    render();
+    topology();
+    adjacency();
+    compare_pricing();
+    replant();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a small deterministic pseudo-random generator, so failures are reproducible
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 33) as usize % bound
+        }
+    }
+
+    const PLANTS: [PlantId; 3] = [PlantId('A'), PlantId('B'), PlantId('C')];
+
+    fn random_map(rng: &mut Lcg) -> Map {
+        let (width, height) = (1 + rng.next(8), 1 + rng.next(8));
+        Map((0..height)
+            .map(|_| (0..width).map(|_| PLANTS[rng.next(3)]).collect())
+            .collect())
+    }
+
+    // the garden's regions must be the ones `find_regions` finds from scratch,
+    // up to their labels, and every label is either in use or free
+    fn check_regions(garden: &Garden) {
+        let (labels, regions) = find_regions(&garden.map, garden.labels.connectivity);
+        let height = garden.map.len() as i32;
+        let width = garden.map[0].len() as i32;
+
+        let mut fresh_to_garden: HashMap<usize, usize> = HashMap::new();
+        let mut garden_to_fresh: HashMap<usize, usize> = HashMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                let pos = IVec2::new(x, y);
+                let fresh = labels.get(pos).unwrap();
+                let label = garden.labels.get(pos).unwrap();
+                assert_eq!(*fresh_to_garden.entry(fresh).or_insert(label), label);
+                assert_eq!(*garden_to_fresh.entry(label).or_insert(fresh), fresh);
+
+                let region = &garden.regions[label];
+                assert_eq!(region.label, label);
+                assert_eq!(region.plant_id, garden.map.get(pos).unwrap());
+                assert!(region.locations.contains(&pos));
+            }
+        }
+        for (&label, &fresh) in &garden_to_fresh {
+            assert_eq!(
+                garden.regions[label].locations.len(),
+                regions[fresh].locations.len()
+            );
+        }
+
+        let mut free = garden.free_labels.clone();
+        free.sort_unstable();
+        free.dedup();
+        assert_eq!(free.len(), garden.free_labels.len(), "label freed twice");
+        for &label in &free {
+            assert!(garden.regions[label].locations.is_empty());
+            assert!(!garden_to_fresh.contains_key(&label));
+        }
+        assert_eq!(garden_to_fresh.len() + free.len(), garden.regions.len());
+    }
+
+    #[test]
+    fn garden_matches_find_regions_under_random_edits() {
+        let mut rng = Lcg(12);
+        for i in 0..400 {
+            let map = random_map(&mut rng);
+            let connectivity = if i % 2 == 0 {
+                Connectivity::Four
+            } else {
+                Connectivity::Eight
+            };
+            let strategy: Box<dyn PricingStrategy> = if i % 4 < 2 {
+                Box::new(SidesPricing)
+            } else {
+                Box::new(PerimeterPricing)
+            };
+            let mut garden = Garden::new(map, connectivity, strategy);
+            check_regions(&garden);
+
+            for _ in 0..40 {
+                let pos = IVec2::new(
+                    rng.next(garden.map[0].len()) as i32,
+                    rng.next(garden.map.len()) as i32,
+                );
+                let plant_id = PLANTS[rng.next(3)];
+                let total_before = garden.total_price();
+                let delta = garden.set(pos, plant_id);
+                check_regions(&garden);
+
+                let (labels, regions) = find_regions(&garden.map, connectivity);
+                let total_after = calc_total_price(&regions, &labels, &*garden.strategy, false);
+                assert_eq!(garden.total_price(), total_after);
+                assert_eq!(delta.delta(), total_after - total_before);
+            }
+        }
+    }
 }
@@ -1,58 +1,45 @@
-use std::{
-    cmp::Reverse,
-    collections::{BinaryHeap, HashSet, VecDeque},
-    ops::{Add, Mul},
-};
-
-use itertools::Itertools;
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct IVec2 {
-    x: i64,
-    y: i64,
+    x: i128,
+    y: i128,
 }
 
 impl IVec2 {
-    fn new(x: i64, y: i64) -> Self {
+    fn new(x: i128, y: i128) -> Self {
         Self { x, y }
     }
-}
 
-impl Add for IVec2 {
-    type Output = Self;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        Self::new(self.x + rhs.x, self.y + rhs.y)
+    fn checked_add(self, rhs: Self) -> Result<Self, Rejection> {
+        Ok(Self::new(
+            checked_add(self.x, rhs.x)?,
+            checked_add(self.y, rhs.y)?,
+        ))
     }
-}
 
-impl Add<i64> for IVec2 {
-    type Output = Self;
-
-    fn add(self, rhs: i64) -> Self::Output {
-        Self::new(self.x + rhs, self.y + rhs)
+    fn checked_mul(self, rhs: i128) -> Result<Self, Rejection> {
+        Ok(Self::new(
+            checked_mul(self.x, rhs)?,
+            checked_mul(self.y, rhs)?,
+        ))
     }
 }
 
-impl Mul<i64> for IVec2 {
-    type Output = Self;
-
-    fn mul(self, rhs: i64) -> Self::Output {
-        Self::new(self.x * rhs, self.y * rhs)
-    }
+#[derive(Debug, Clone, Copy)]
+struct Button {
+    delta: IVec2,
+    cost: i128,
 }
 
 #[derive(Debug)]
 struct ClawMachine {
-    a_claw: IVec2,
-    b_claw: IVec2,
+    buttons: Vec<Button>,
     prize_pos: IVec2,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
 struct ButtonPresses {
-    a: i64,
-    b: i64,
+    presses: Vec<i128>,
+    cost: i128,
 }
 
 impl PartialOrd for ButtonPresses {
@@ -63,68 +50,486 @@ impl PartialOrd for ButtonPresses {
 
 impl Ord for ButtonPresses {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let self_total = self.a * 3 + self.b;
-        let other_total = other.a * 3 + other.b;
-        self_total.cmp(&other_total)
+        self.cost
+            .cmp(&other.cost)
+            .then_with(|| self.presses.cmp(&other.presses))
     }
 }
 
+impl ButtonPresses {
+    fn new(presses: Vec<i128>, buttons: &[Button]) -> Result<Self, Rejection> {
+        let mut cost = 0;
+        for (&count, button) in presses.iter().zip(buttons) {
+            cost = checked_add(cost, checked_mul(count, button.cost)?)?;
+        }
+        Ok(Self { presses, cost })
+    }
+}
+
+// the original puzzle: A costs 3 tokens, B costs 1
+const TOKEN_COSTS: &[(&str, i128)] = &[("A", 3), ("B", 1)];
+
+const ERROR_CORRECTION: i128 = 10000000000000;
+
 fn get_input(file_path: &str) -> String {
     std::fs::read_to_string(file_path).unwrap()
 }
 
+#[derive(Debug)]
+struct ParseError {
+    // 1-based index of the machine block and of the line in the whole input
+    block: usize,
+    line: usize,
+    message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "block {}, line {}: {}",
+            self.block, self.line, self.message
+        )
+    }
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { rest: input }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.skip_whitespace();
+        self.rest.is_empty()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else if self.rest.is_empty() {
+            Err(format!("expected '{}' at end of line", c))
+        } else {
+            Err(format!("expected '{}' at '{}'", c, self.rest))
+        }
+    }
+
+    fn take_until(&mut self, c: char) -> &'a str {
+        self.skip_whitespace();
+        let end = self.rest.find(c).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken.trim_end()
+    }
+
+    fn word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        word
+    }
+
+    fn integer(&mut self) -> Result<i128, String> {
+        let negative = if self.eat('-') {
+            true
+        } else {
+            self.eat('+');
+            false
+        };
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let (digits, rest) = self.rest.split_at(end);
+        if digits.is_empty() {
+            return Err(format!("expected a number at '{}'", self.rest));
+        }
+        self.rest = rest;
+        let value: i128 = digits
+            .parse()
+            .map_err(|_| format!("number '{}' is too large", digits))?;
+        Ok(if negative { -value } else { value })
+    }
+}
+
+enum Line<'a> {
+    Button { label: &'a str, delta: IVec2 },
+    Prize(IVec2),
+}
+
+// line    := "Button" label ":" coords | "Prize" ":" coords
+// coords  := coord "," coord
+// coord   := axis ["="] integer
+// axis    := "X" | "Y"
+// integer := ["+" | "-"] digit+
+fn parse_line(line: &str) -> Result<Line<'_>, String> {
+    let mut cursor = Cursor::new(line);
+    let line = match cursor.word() {
+        "Button" => {
+            let label = cursor.take_until(':');
+            if label.is_empty() {
+                return Err("missing button label".to_string());
+            }
+            cursor.expect(':')?;
+            let delta = parse_coords(&mut cursor)?;
+            Line::Button { label, delta }
+        }
+        "Prize" => {
+            cursor.expect(':')?;
+            Line::Prize(parse_coords(&mut cursor)?)
+        }
+        other => return Err(format!("expected 'Button' or 'Prize', found '{}'", other)),
+    };
+
+    if !cursor.is_empty() {
+        return Err(format!("unexpected trailing input '{}'", cursor.rest));
+    }
+    Ok(line)
+}
+
+fn parse_coords(cursor: &mut Cursor) -> Result<IVec2, String> {
+    let mut x = None;
+    let mut y = None;
+    for i in 0..2 {
+        if i > 0 {
+            cursor.expect(',')?;
+        }
+        let axis = cursor.word();
+        let slot = match axis {
+            "X" | "x" => &mut x,
+            "Y" | "y" => &mut y,
+            _ => return Err(format!("expected axis 'X' or 'Y', found '{}'", axis)),
+        };
+        if slot.is_some() {
+            return Err(format!("axis '{}' given twice", axis));
+        }
+        cursor.eat('=');
+        *slot = Some(cursor.integer()?);
+    }
+    // both loop iterations filled a distinct slot
+    Ok(IVec2::new(x.unwrap(), y.unwrap()))
+}
+
 // Button A: X+94, Y+34
 // Button B: X+22, Y+67
 // Prize: X=8400, Y=5400
-fn parse_input(input: &str, error_correction: bool) -> Vec<ClawMachine> {
-    input
-        .split("\n\n")
-        .map(|machine| {
-            let lines: Vec<&str> = machine.lines().collect::<Vec<&str>>();
-            let a_claw = lines[0]
-                .split_once(": ")
-                .unwrap()
-                .1
-                .split_once(", ")
-                .unwrap();
-            let a_claw = IVec2::new(
-                a_claw.0.split_once("+").unwrap().1.parse().unwrap(),
-                a_claw.1.split_once("+").unwrap().1.parse().unwrap(),
-            );
+//
+// machines are separated by blank lines. each machine has any number of
+// uniquely labelled buttons and exactly one prize, in any order. `costs` maps
+// each button label to its token cost
+fn parse_machine(
+    block: usize,
+    lines: &[(usize, &str)],
+    offset: i128,
+    costs: &[(&str, i128)],
+) -> Result<ClawMachine, ParseError> {
+    let error = |line: usize, message: String| ParseError {
+        block,
+        line,
+        message,
+    };
 
-            let b_claw = lines[1]
-                .split_once(": ")
-                .unwrap()
-                .1
-                .split_once(", ")
-                .unwrap();
-            let b_claw = IVec2::new(
-                b_claw.0.split_once("+").unwrap().1.parse().unwrap(),
-                b_claw.1.split_once("+").unwrap().1.parse().unwrap(),
-            );
+    let mut labels = Vec::new();
+    let mut buttons = Vec::new();
+    let mut prize_pos = None;
+    for &(line_no, line) in lines {
+        match parse_line(line).map_err(|message| error(line_no, message))? {
+            Line::Button { label, delta } => {
+                if labels.contains(&label) {
+                    return Err(error(line_no, format!("duplicate button '{}'", label)));
+                }
+                let cost = costs
+                    .iter()
+                    .find(|&&(name, _)| name == label)
+                    .map(|&(_, cost)| cost)
+                    .ok_or_else(|| {
+                        error(
+                            line_no,
+                            format!("no cost configured for button '{}'", label),
+                        )
+                    })?;
+                labels.push(label);
+                buttons.push(Button { delta, cost });
+            }
+            Line::Prize(pos) => {
+                if prize_pos.is_some() {
+                    return Err(error(line_no, "duplicate prize".to_string()));
+                }
+                let pos = pos
+                    .checked_add(IVec2::new(offset, offset))
+                    .map_err(|_| error(line_no, "prize position overflows".to_string()))?;
+                prize_pos = Some(pos);
+            }
+        }
+    }
 
-            let prize_pos = lines[2]
-                .split_once(": ")
-                .unwrap()
-                .1
-                .split_once(", ")
-                .unwrap();
+    let prize_pos = prize_pos.ok_or_else(|| error(lines[0].0, "missing prize".to_string()))?;
+    Ok(ClawMachine { buttons, prize_pos })
+}
 
-            let prize_pos = IVec2::new(
-                prize_pos.0.split_once("=").unwrap().1.parse().unwrap(),
-                prize_pos.1.split_once("=").unwrap().1.parse().unwrap(),
-            ) + if error_correction { 10000000000000 } else { 0 };
+fn parse_input(
+    input: &str,
+    error_correction: bool,
+    costs: &[(&str, i128)],
+) -> Result<Vec<ClawMachine>, ParseError> {
+    let offset = if error_correction {
+        ERROR_CORRECTION
+    } else {
+        0
+    };
 
-            ClawMachine {
-                a_claw,
-                b_claw,
-                prize_pos,
+    // `lines` also strips the '\r' of CRLF line endings
+    let mut blocks: Vec<Vec<(usize, &str)>> = vec![Vec::new()];
+    for (i, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            if !blocks.last().unwrap().is_empty() {
+                blocks.push(Vec::new());
             }
-        })
+        } else {
+            blocks.last_mut().unwrap().push((i + 1, line));
+        }
+    }
+
+    blocks
+        .iter()
+        .filter(|lines| !lines.is_empty())
+        .enumerate()
+        .map(|(i, lines)| parse_machine(i + 1, lines, offset, costs))
         .collect()
 }
 
-fn get_minimal_cost(machine: &ClawMachine) -> Option<i64> {
+fn checked_add(a: i128, b: i128) -> Result<i128, Rejection> {
+    a.checked_add(b).ok_or(Rejection::Overflow)
+}
+
+fn checked_sub(a: i128, b: i128) -> Result<i128, Rejection> {
+    a.checked_sub(b).ok_or(Rejection::Overflow)
+}
+
+fn checked_mul(a: i128, b: i128) -> Result<i128, Rejection> {
+    a.checked_mul(b).ok_or(Rejection::Overflow)
+}
+
+// returns (g, x, y) such that a*x + b*y = g = gcd(a, b)
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        if a < 0 {
+            (-a, -1, 0)
+        } else {
+            (a, 1, 0)
+        }
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -div_floor(-a, b)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SolverOptions {
+    // reject solutions with negative presses. without it, a machine with more
+    // than two buttons usually has no cheapest solution and is Unbounded
+    non_negative: bool,
+    // reject solutions that press any button more often than this
+    max_presses: Option<i128>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Rejection {
+    // no integer number of presses lands exactly on the prize
+    NoIntegerSolution,
+    // the buttons are parallel and the prize is not on their line
+    PrizeOffLine,
+    // integer solutions exist, but none satisfies the press constraints
+    NoSolutionInRange,
+    NegativePresses(Vec<i128>),
+    PressLimitExceeded { presses: Vec<i128>, limit: i128 },
+    // a button has a negative cost, so there may be no cheapest solution
+    NegativeCost,
+    // the presses are not bounded in a direction that keeps lowering the
+    // cost, e.g. because presses may be negative
+    Unbounded,
+    // a button can move the claw backwards and no press limit was given, so
+    // the free parameters beyond the first two cannot be bounded for the search
+    NoPressBound,
+    // more than SEARCH_LIMIT combinations of presses would have to be tried
+    SearchTooLarge,
+    // an intermediate value does not fit in an i128
+    Overflow,
+}
+
+// narrows k so that base + k*step >= bound, returns false if no k can satisfy it
+fn restrict_at_least(
+    k_min: &mut i128,
+    k_max: &mut i128,
+    base: i128,
+    step: i128,
+    bound: i128,
+) -> Result<bool, Rejection> {
+    match step.cmp(&0) {
+        std::cmp::Ordering::Greater => {
+            *k_min = (*k_min).max(div_ceil(checked_sub(bound, base)?, step))
+        }
+        std::cmp::Ordering::Less => {
+            *k_max = (*k_max).min(div_floor(checked_sub(bound, base)?, step))
+        }
+        std::cmp::Ordering::Equal => return Ok(base >= bound),
+    }
+    Ok(true)
+}
+
+// cheapest non-negative (i, j) with i*a + j*b = p, optionally capped at `max_presses`
+fn solve_1d(
+    (a, b): (i128, i128),
+    (cost_a, cost_b): (i128, i128),
+    p: i128,
+    max_presses: Option<i128>,
+) -> Result<[i128; 2], Rejection> {
+    if a == 0 && b == 0 {
+        return if p == 0 {
+            Ok([0, 0])
+        } else {
+            Err(Rejection::NoIntegerSolution)
+        };
+    }
+
+    let (g, x, y) = extended_gcd(a, b);
+    if p % g != 0 {
+        return Err(Rejection::NoIntegerSolution);
+    }
+
+    // all solutions are i = i0 + k*step_i, j = j0 - k*step_j
+    let i0 = checked_mul(x, p / g)?;
+    let j0 = checked_mul(y, p / g)?;
+    let step_i = b / g;
+    let step_j = a / g;
+
+    let mut k_min = i128::MIN;
+    let mut k_max = i128::MAX;
+    for (base, step) in [(i0, step_i), (j0, -step_j)] {
+        let mut feasible = restrict_at_least(&mut k_min, &mut k_max, base, step, 0)?;
+        if let Some(limit) = max_presses {
+            feasible &= restrict_at_least(&mut k_min, &mut k_max, -base, -step, -limit)?;
+        }
+        if !feasible {
+            return Err(Rejection::NoSolutionInRange);
+        }
+    }
+    if k_min > k_max {
+        return Err(Rejection::NoSolutionInRange);
+    }
+
+    // cost is linear in k, so the cheapest solution sits at one end of the range.
+    // an unbounded end only exists when both presses grow in that direction,
+    // so with non-negative costs the bounded end is the cheaper one
+    let mut best: Option<(i128, [i128; 2])> = None;
+    for k in [k_min, k_max] {
+        if k == i128::MIN || k == i128::MAX {
+            continue;
+        }
+        let i = checked_add(i0, checked_mul(k, step_i)?)?;
+        let j = checked_sub(j0, checked_mul(k, step_j)?)?;
+        let cost = checked_add(checked_mul(i, cost_a)?, checked_mul(j, cost_b)?)?;
+        if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+            best = Some((cost, [i, j]));
+        }
+    }
+
+    best.map(|(_, presses)| presses)
+        .ok_or(Rejection::NoSolutionInRange)
+}
+
+fn cross(u: IVec2, v: IVec2) -> Result<i128, Rejection> {
+    checked_sub(checked_mul(u.x, v.y)?, checked_mul(u.y, v.x)?)
+}
+
+// A and B are parallel, so the prize must lie on the same line and the
+// problem reduces to a single diophantine equation along one axis.
+// presses are always non-negative here, otherwise there is no cheapest solution
+fn solve_collinear(
+    a: Button,
+    b: Button,
+    prize: IVec2,
+    options: SolverOptions,
+) -> Result<[i128; 2], Rejection> {
+    if cross(a.delta, prize)? != 0 || cross(b.delta, prize)? != 0 {
+        return Err(Rejection::PrizeOffLine);
+    }
+
+    let costs = (a.cost, b.cost);
+    let ([i, j], other_axis) = if a.delta.x != 0 || b.delta.x != 0 {
+        let presses = solve_1d((a.delta.x, b.delta.x), costs, prize.x, options.max_presses)?;
+        (presses, (a.delta.y, b.delta.y, prize.y))
+    } else {
+        let presses = solve_1d((a.delta.y, b.delta.y), costs, prize.y, options.max_presses)?;
+        (presses, (a.delta.x, b.delta.x, prize.x))
+    };
+
+    let (oa, ob, op) = other_axis;
+    if checked_add(checked_mul(i, oa)?, checked_mul(j, ob)?)? == op {
+        Ok([i, j])
+    } else {
+        Err(Rejection::PrizeOffLine)
+    }
+}
+
+fn check_constraints(presses: &[i128], options: SolverOptions) -> Result<(), Rejection> {
+    if options.non_negative && presses.iter().any(|&count| count < 0) {
+        return Err(Rejection::NegativePresses(presses.to_vec()));
+    }
+    if let Some(limit) = options.max_presses {
+        if presses.iter().any(|&count| count > limit) {
+            return Err(Rejection::PressLimitExceeded {
+                presses: presses.to_vec(),
+                limit,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn solve_pair(
+    a: Button,
+    b: Button,
+    prize: IVec2,
+    options: SolverOptions,
+) -> Result<[i128; 2], Rejection> {
     // px = i*ax + j*bx
     // py = i*ay + j*by
     // A = [ax bx] x = [i]
@@ -138,55 +543,891 @@ fn get_minimal_cost(machine: &ClawMachine) -> Option<i64> {
     //
     // i = Di/D
     // j = Dj/D
-    // answer = 3*i + j
-
-    let ax = machine.a_claw.x;
-    let ay = machine.a_claw.y;
-    let bx = machine.b_claw.x;
-    let by = machine.b_claw.y;
-    let px = machine.prize_pos.x;
-    let py = machine.prize_pos.y;
-
-    let d = ax * by - ay * bx;
-    let di = px * by - py * bx;
-    let dj = py * ax - px * ay;
-
-    if di % d == 0 && dj % d == 0 {
-        let i = di / d;
-        let j = dj / d;
-        Some(3 * i + j)
-    } else {
-        None
+
+    let d = cross(a.delta, b.delta)?;
+    if d == 0 {
+        return solve_collinear(a, b, prize, options);
+    }
+
+    let di = cross(prize, b.delta)?;
+    let dj = cross(a.delta, prize)?;
+
+    if di % d != 0 || dj % d != 0 {
+        return Err(Rejection::NoIntegerSolution);
     }
+
+    let presses = [di / d, dj / d];
+    check_constraints(&presses, options)?;
+    Ok(presses)
 }
 
-fn part01() {
-    let input = get_input("input.txt");
-    let machines = parse_input(&input, false);
+fn solve_single(button: Button, prize: IVec2, options: SolverOptions) -> Result<i128, Rejection> {
+    let delta = button.delta;
+    let count = match (delta.x, delta.y) {
+        (0, 0) => 0,
+        (0, dy) if prize.y % dy == 0 => prize.y / dy,
+        (dx, _) if dx != 0 && prize.x % dx == 0 => prize.x / dx,
+        _ => return Err(Rejection::NoIntegerSolution),
+    };
+    if delta.checked_mul(count)? != prize {
+        return Err(Rejection::NoIntegerSolution);
+    }
+    check_constraints(&[count], options)?;
+    Ok(count)
+}
 
-    let mut total_cost = 0;
-    for machine in machines {
-        let minimal_cost = get_minimal_cost(&machine);
-        total_cost += minimal_cost.unwrap_or(0);
+// the most presses `button` can take before it overshoots the prize,
+// only known up front when no button can move the claw backwards
+fn press_bound(
+    button: Button,
+    buttons: &[Button],
+    prize: IVec2,
+    options: SolverOptions,
+) -> Result<i128, Rejection> {
+    if let Some(limit) = options.max_presses {
+        return Ok(limit);
+    }
+    if buttons.iter().any(|b| b.delta.x < 0 || b.delta.y < 0) {
+        return Err(Rejection::NoPressBound);
     }
 
-    println!("Total cost: {}", total_cost);
+    let delta = button.delta;
+    let bound = [(delta.x, prize.x), (delta.y, prize.y)]
+        .into_iter()
+        .filter(|&(step, _)| step > 0)
+        .map(|(step, target)| div_floor(target, step))
+        .min();
+
+    // a button that does not move the claw is never worth pressing
+    Ok(bound.unwrap_or(0))
 }
 
-fn part02() {
-    let input = get_input("input.txt");
-    let machines = parse_input(&input, true);
+// the most combinations of free parameters `solve_buttons` will try
+const SEARCH_LIMIT: i128 = 1_000_000;
+
+// every integer solution of sum(presses[i] * delta[i]) = prize, written as
+// base + sum(t[j] * kernel[j]) for integers t[j]. `inverse[j]` recovers t[j]
+// from the presses of a solution
+#[derive(Debug)]
+struct Lattice {
+    base: Vec<i128>,
+    kernel: Vec<Vec<i128>>,
+    inverse: Vec<Vec<i128>>,
+}
+
+impl Lattice {
+    // brings the 2xN system into hermite normal form with unimodular column
+    // operations, tracking them in `u` and their inverse in `u_inv`
+    fn new(buttons: &[Button], prize: IVec2) -> Result<Self, Rejection> {
+        let n = buttons.len();
+        let identity: Vec<Vec<i128>> = (0..n)
+            .map(|i| (0..n).map(|j| (i == j) as i128).collect())
+            .collect();
+        let mut rows = [
+            buttons.iter().map(|b| b.delta.x).collect::<Vec<_>>(),
+            buttons.iter().map(|b| b.delta.y).collect::<Vec<_>>(),
+        ];
+        let mut u = identity.clone();
+        let mut u_inv = identity;
+
+        // pivots[row] is the column holding that row's leading entry
+        let mut pivots = [None; 2];
+        let mut rank = 0;
+        for (row, pivot) in pivots.iter_mut().enumerate() {
+            if rank == n {
+                break;
+            }
+            for j in rank + 1..n {
+                let (p, q) = (rows[row][rank], rows[row][j]);
+                if q == 0 {
+                    continue;
+                }
+                // [col_rank, col_j] <- [x*col_rank + y*col_j, q*col_rank - p*col_j]
+                let (g, x, y) = extended_gcd(p, q);
+                let (p, q) = (p / g, q / g);
+                for vec in rows.iter_mut().chain(u.iter_mut()) {
+                    let (a, b) = (vec[rank], vec[j]);
+                    vec[rank] = checked_add(checked_mul(x, a)?, checked_mul(y, b)?)?;
+                    vec[j] = checked_sub(checked_mul(q, a)?, checked_mul(p, b)?)?;
+                }
+                let (head, tail) = u_inv.split_at_mut(j);
+                for (first, second) in head[rank].iter_mut().zip(tail[0].iter_mut()) {
+                    let (a, b) = (*first, *second);
+                    *first = checked_add(checked_mul(p, a)?, checked_mul(q, b)?)?;
+                    *second = checked_sub(checked_mul(y, a)?, checked_mul(x, b)?)?;
+                }
+            }
+            if rows[row][rank] != 0 {
+                *pivot = Some(rank);
+                rank += 1;
+            }
+        }
+
+        // forward substitution for the pivot columns
+        let mut fixed = vec![0; rank];
+        for (row, target) in [prize.x, prize.y].into_iter().enumerate() {
+            let mut residual = target;
+            for (c, &value) in fixed.iter().enumerate() {
+                residual = checked_sub(residual, checked_mul(rows[row][c], value)?)?;
+            }
+            match pivots[row] {
+                Some(c) if residual % rows[row][c] == 0 => fixed[c] = residual / rows[row][c],
+                None if residual == 0 => {}
+                _ => return Err(Rejection::NoIntegerSolution),
+            }
+        }
+
+        let mut base = vec![0; n];
+        for (i, count) in base.iter_mut().enumerate() {
+            for (c, &value) in fixed.iter().enumerate() {
+                *count = checked_add(*count, checked_mul(u[i][c], value)?)?;
+            }
+        }
+        let kernel = (rank..n)
+            .map(|c| u.iter().map(|row| row[c]).collect())
+            .collect();
+        let inverse = u_inv.split_off(rank);
+
+        Ok(Self {
+            base,
+            kernel,
+            inverse,
+        })
+    }
+}
+
+// cheapest solution presses + t*direction over all integers t that satisfy
+// the press constraints, None if there is none
+fn solve_line(
+    presses: &[i128],
+    direction: &[i128],
+    buttons: &[Button],
+    options: SolverOptions,
+) -> Result<Option<ButtonPresses>, Rejection> {
+    let mut t_min = i128::MIN;
+    let mut t_max = i128::MAX;
+    for (&base, &step) in presses.iter().zip(direction) {
+        let mut feasible = true;
+        if options.non_negative {
+            feasible &= restrict_at_least(&mut t_min, &mut t_max, base, step, 0)?;
+        }
+        if let Some(limit) = options.max_presses {
+            feasible &= restrict_at_least(&mut t_min, &mut t_max, -base, -step, -limit)?;
+        }
+        if !feasible {
+            return Ok(None);
+        }
+    }
+    if t_min > t_max {
+        return Ok(None);
+    }
+
+    // the cost is linear in t, so the cheapest solution sits at one end
+    let mut best: Option<ButtonPresses> = None;
+    for t in [t_min, t_max] {
+        if t == i128::MIN || t == i128::MAX {
+            continue;
+        }
+        let mut candidate = Vec::with_capacity(presses.len());
+        for (&base, &step) in presses.iter().zip(direction) {
+            candidate.push(checked_add(base, checked_mul(t, step)?)?);
+        }
+        let candidate = ButtonPresses::new(candidate, buttons)?;
+        if best.as_ref().is_none_or(|best| candidate < *best) {
+            best = Some(candidate);
+        }
+    }
+
+    let mut slope = 0;
+    for (&step, button) in direction.iter().zip(buttons) {
+        slope = checked_add(slope, checked_mul(step, button.cost)?)?;
+    }
+    let unbounded = match slope.cmp(&0) {
+        std::cmp::Ordering::Less => t_max == i128::MAX,
+        std::cmp::Ordering::Greater => t_min == i128::MIN,
+        std::cmp::Ordering::Equal => best.is_none(),
+    };
+    if unbounded {
+        return Err(Rejection::Unbounded);
+    }
+    Ok(best)
+}
+
+// the range of t = sum(row[i] * presses[i]) over all presses within their bounds
+fn param_range(row: &[i128], bounds: &[i128]) -> Result<(i128, i128), Rejection> {
+    let (mut lo, mut hi) = (0, 0);
+    for (&coeff, &bound) in row.iter().zip(bounds) {
+        let end = checked_mul(coeff, bound)?;
+        lo = checked_add(lo, end.min(0))?;
+        hi = checked_add(hi, end.max(0))?;
+    }
+    Ok((lo, hi))
+}
+
+// the half-plane a*s + b*t + offset >= 0 of two free parameters
+#[derive(Debug, Clone, Copy)]
+struct HalfPlane {
+    a: i128,
+    b: i128,
+    offset: i128,
+}
+
+impl HalfPlane {
+    fn contains(&self, w: (i128, i128)) -> Result<bool, Rejection> {
+        Ok(checked_add(checked_mul(self.a, w.0)?, checked_mul(self.b, w.1)?)? >= 0)
+    }
+}
+
+// a numerator and a positive denominator
+type Fraction = (i128, i128);
+
+// the lower and upper end of a real range, None where it is open
+type Interval = (Option<Fraction>, Option<Fraction>);
+
+// x < y
+fn less(x: Fraction, y: Fraction) -> Result<bool, Rejection> {
+    Ok(checked_mul(x.0, y.1)? < checked_mul(y.0, x.1)?)
+}
+
+// the range of real t within every half-plane for a fixed s, None if there is
+// no such t and an open end if nothing bounds t in that direction
+fn t_range(constraints: &[HalfPlane], s: i128) -> Result<Option<Interval>, Rejection> {
+    let mut lower: Option<Fraction> = None;
+    let mut upper: Option<Fraction> = None;
+    for c in constraints {
+        // b*t >= -(a*s + offset)
+        let rhs = -checked_add(checked_mul(c.a, s)?, c.offset)?;
+        match c.b.cmp(&0) {
+            std::cmp::Ordering::Greater => {
+                let end = (rhs, c.b);
+                if lower.map_or(Ok(true), |lower| less(lower, end))? {
+                    lower = Some(end);
+                }
+            }
+            std::cmp::Ordering::Less => {
+                let end = (-rhs, -c.b);
+                if upper.map_or(Ok(true), |upper| less(end, upper))? {
+                    upper = Some(end);
+                }
+            }
+            std::cmp::Ordering::Equal if rhs > 0 => return Ok(None),
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    if let (Some(lower), Some(upper)) = (lower, upper) {
+        if less(upper, lower)? {
+            return Ok(None);
+        }
+    }
+    Ok(Some((lower, upper)))
+}
+
+fn dot(u: &[i128], v: &[i128]) -> Result<i128, Rejection> {
+    u.iter()
+        .zip(v)
+        .try_fold(0, |acc, (&a, &b)| checked_add(acc, checked_mul(a, b)?))
+}
 
-    let mut total_cost = 0;
-    for machine in machines {
-        let minimal_cost = get_minimal_cost(&machine);
-        total_cost += minimal_cost.unwrap_or(0);
+// lagrange reduction of the basis (u, v) of a 2d lattice, leaving u and v as
+// short and as close to orthogonal as the lattice allows, which keeps the
+// polygon `solve_plane` scans from being a thin sliver in its parameters
+fn reduce_basis(u: &[i128], v: &[i128]) -> Result<(Vec<i128>, Vec<i128>), Rejection> {
+    let (mut u, mut v) = (u.to_vec(), v.to_vec());
+    if dot(&v, &v)? < dot(&u, &u)? {
+        std::mem::swap(&mut u, &mut v);
     }
+    loop {
+        // v - mu*u is the shortest of the vectors v - k*u
+        let uu = dot(&u, &u)?;
+        let mu = div_floor(
+            checked_add(checked_mul(2, dot(&u, &v)?)?, uu)?,
+            checked_mul(2, uu)?,
+        );
+        for (b, &a) in v.iter_mut().zip(&u) {
+            *b = checked_sub(*b, checked_mul(mu, a)?)?;
+        }
+        if dot(&v, &v)? >= uu {
+            return Ok((u, v));
+        }
+        std::mem::swap(&mut u, &mut v);
+    }
+}
+
+// cheapest solution presses + s*u + t*v over all integers s and t that satisfy
+// the press constraints, None if there is none.
+// the constraints cut out a polygon in (s, t) and the cheapest real point is
+// one of its corners. the cheapest real t for a fixed s is a convex function
+// of s, so s is scanned outwards from that corner, solving t in closed form,
+// until the polygon ends or even real points cost as much as the best found
+fn solve_plane(
+    presses: &[i128],
+    (u, v): (&[i128], &[i128]),
+    buttons: &[Button],
+    options: SolverOptions,
+) -> Result<Option<ButtonPresses>, Rejection> {
+    let mut constraints = Vec::new();
+    for ((&base, &a), &b) in presses.iter().zip(u).zip(v) {
+        if options.non_negative {
+            constraints.push(HalfPlane { a, b, offset: base });
+        }
+        if let Some(limit) = options.max_presses {
+            constraints.push(HalfPlane {
+                a: -a,
+                b: -b,
+                offset: checked_sub(limit, base)?,
+            });
+        }
+    }
+    // constraints that do not depend on s or t either always or never hold
+    if constraints
+        .iter()
+        .any(|c| c.a == 0 && c.b == 0 && c.offset < 0)
+    {
+        return Ok(None);
+    }
+    constraints.retain(|c| c.a != 0 || c.b != 0);
+    if constraints.is_empty() {
+        return Err(Rejection::Unbounded);
+    }
+
+    let mut base_cost = 0;
+    let (mut cost_s, mut cost_t) = (0, 0);
+    for (((&base, &a), &b), button) in presses.iter().zip(u).zip(v).zip(buttons) {
+        base_cost = checked_add(base_cost, checked_mul(base, button.cost)?)?;
+        cost_s = checked_add(cost_s, checked_mul(a, button.cost)?)?;
+        cost_t = checked_add(cost_t, checked_mul(b, button.cost)?)?;
+    }
+
+    // the cheapest corner as (s, t, denominator)
+    let mut corner: Option<(i128, i128, i128)> = None;
+    for (i, p) in constraints.iter().enumerate() {
+        for q in &constraints[i + 1..] {
+            let mut det = checked_sub(checked_mul(p.a, q.b)?, checked_mul(q.a, p.b)?)?;
+            if det == 0 {
+                continue;
+            }
+            let mut s = checked_sub(checked_mul(q.offset, p.b)?, checked_mul(p.offset, q.b)?)?;
+            let mut t = checked_sub(checked_mul(p.offset, q.a)?, checked_mul(q.offset, p.a)?)?;
+            if det < 0 {
+                (s, t, det) = (-s, -t, -det);
+            }
+
+            let mut inside = true;
+            for c in &constraints {
+                let value = checked_add(
+                    checked_add(checked_mul(c.a, s)?, checked_mul(c.b, t)?)?,
+                    checked_mul(c.offset, det)?,
+                )?;
+                inside &= value >= 0;
+            }
+            if !inside {
+                continue;
+            }
+
+            let cost = checked_add(checked_mul(cost_s, s)?, checked_mul(cost_t, t)?)?;
+            let cheaper = match corner {
+                None => true,
+                Some((best_s, best_t, best_det)) => {
+                    let best_cost =
+                        checked_add(checked_mul(cost_s, best_s)?, checked_mul(cost_t, best_t)?)?;
+                    less((cost, det), (best_cost, best_det))?
+                }
+            };
+            if cheaper {
+                corner = Some((s, t, det));
+            }
+        }
+    }
+    let Some((corner_s, _, corner_det)) = corner else {
+        return Ok(None);
+    };
 
-    println!("Total cost: {}", total_cost);
+    // the cost keeps falling along some direction that stays inside the polygon
+    let mut directions = vec![(-cost_s, -cost_t)];
+    for c in &constraints {
+        directions.extend([(-c.b, c.a), (c.b, -c.a)]);
+    }
+    for w in directions {
+        let slope = checked_add(checked_mul(cost_s, w.0)?, checked_mul(cost_t, w.1)?)?;
+        if slope >= 0 {
+            continue;
+        }
+        let mut inside = true;
+        for c in &constraints {
+            inside &= c.contains(w)?;
+        }
+        if inside {
+            return Err(Rejection::Unbounded);
+        }
+    }
+
+    let mut best: Option<ButtonPresses> = None;
+    let mut steps = 0;
+    let start = div_floor(corner_s, corner_det);
+    for (mut s, step) in [(start, -1), (checked_add(start, 1)?, 1)] {
+        loop {
+            steps += 1;
+            if steps > SEARCH_LIMIT {
+                return Err(Rejection::SearchTooLarge);
+            }
+
+            let Some((lower, upper)) = t_range(&constraints, s)? else {
+                break;
+            };
+            // the cheapest real t for this s as a fraction
+            let end = match cost_t.cmp(&0) {
+                std::cmp::Ordering::Greater => lower,
+                std::cmp::Ordering::Less => upper,
+                std::cmp::Ordering::Equal => Some((0, 1)),
+            };
+            let (t, den) = end.ok_or(Rejection::Unbounded)?;
+            if let Some(best) = &best {
+                let fixed = checked_add(base_cost, checked_mul(cost_s, s)?)?;
+                let bound = checked_add(checked_mul(fixed, den)?, checked_mul(cost_t, t)?)?;
+                if bound >= checked_mul(best.cost, den)? {
+                    break;
+                }
+            }
+
+            let mut line = presses.to_vec();
+            for (count, &a) in line.iter_mut().zip(u) {
+                *count = checked_add(*count, checked_mul(s, a)?)?;
+            }
+            if let Some(candidate) = solve_line(&line, v, buttons, options)? {
+                if best.as_ref().is_none_or(|best| candidate < *best) {
+                    best = Some(candidate);
+                }
+            }
+            s = checked_add(s, step)?;
+        }
+    }
+    Ok(best)
+}
+
+// exact integer programming over the solution lattice: with one free
+// parameter the cheapest solution is found in closed form, with two by a
+// bounded scan of the plane they span. any further free parameters are
+// enumerated within the range the press bounds allow
+fn solve_buttons(
+    buttons: &[Button],
+    prize: IVec2,
+    options: SolverOptions,
+) -> Result<Vec<i128>, Rejection> {
+    match buttons {
+        [] if prize == IVec2::new(0, 0) => return Ok(Vec::new()),
+        [] => return Err(Rejection::NoIntegerSolution),
+        [a] => return solve_single(*a, prize, options).map(|count| vec![count]),
+        [a, b] => return solve_pair(*a, *b, prize, options).map(|presses| presses.to_vec()),
+        _ => {}
+    }
+
+    let lattice = Lattice::new(buttons, prize)?;
+    let (u, v, outer) = match &lattice.kernel[..] {
+        [] => {
+            check_constraints(&lattice.base, options)?;
+            return Ok(lattice.base);
+        }
+        [u] => {
+            return solve_line(&lattice.base, u, buttons, options)?
+                .map(|best| best.presses)
+                .ok_or(Rejection::NoSolutionInRange);
+        }
+        [u, v, outer @ ..] => {
+            let (u, v) = reduce_basis(u, v)?;
+            (u, v, outer)
+        }
+    };
+
+    let mut ranges = Vec::with_capacity(outer.len());
+    if !outer.is_empty() {
+        if !options.non_negative {
+            return Err(Rejection::Unbounded);
+        }
+        let bounds = buttons
+            .iter()
+            .map(|&button| press_bound(button, buttons, prize, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut combinations: i128 = 1;
+        for row in &lattice.inverse[2..] {
+            let (lo, hi) = param_range(row, &bounds)?;
+            combinations = checked_mul(combinations, checked_add(checked_sub(hi, lo)?, 1)?)?;
+            if combinations > SEARCH_LIMIT {
+                return Err(Rejection::SearchTooLarge);
+            }
+            ranges.push((lo, hi));
+        }
+    }
+
+    let mut best: Option<ButtonPresses> = None;
+    let mut params: Vec<i128> = ranges.iter().map(|&(lo, _)| lo).collect();
+    loop {
+        let mut presses = lattice.base.clone();
+        for (&t, direction) in params.iter().zip(outer) {
+            for (count, &step) in presses.iter_mut().zip(direction) {
+                *count = checked_add(*count, checked_mul(t, step)?)?;
+            }
+        }
+        if let Some(candidate) = solve_plane(&presses, (&u, &v), buttons, options)? {
+            if best.as_ref().is_none_or(|best| candidate < *best) {
+                best = Some(candidate);
+            }
+        }
+
+        // advance the outer free parameters like an odometer
+        let mut i = 0;
+        while i < params.len() && params[i] == ranges[i].1 {
+            params[i] = ranges[i].0;
+            i += 1;
+        }
+        if i == params.len() {
+            break;
+        }
+        params[i] += 1;
+    }
+
+    best.map(|best| best.presses)
+        .ok_or(Rejection::NoSolutionInRange)
+}
+
+fn solve_machine(
+    machine: &ClawMachine,
+    options: SolverOptions,
+) -> Result<ButtonPresses, Rejection> {
+    if machine.buttons.iter().any(|button| button.cost < 0) {
+        return Err(Rejection::NegativeCost);
+    }
+
+    let presses = solve_buttons(&machine.buttons, machine.prize_pos, options)?;
+    ButtonPresses::new(presses, &machine.buttons)
+}
+
+// the cheapest presses for every machine, numbered from 1, or why it was rejected
+fn solve_machines(
+    machines: &[ClawMachine],
+    options: SolverOptions,
+) -> Vec<(usize, Result<ButtonPresses, Rejection>)> {
+    machines
+        .iter()
+        .enumerate()
+        .map(|(i, machine)| (i + 1, solve_machine(machine, options)))
+        .collect()
+}
+
+// rejected machines add nothing to the total, but an overflow anywhere
+// fails the whole total rather than silently dropping that machine
+fn total_cost(results: &[(usize, Result<ButtonPresses, Rejection>)]) -> Result<i128, Rejection> {
+    let mut total = 0;
+    for (_, result) in results {
+        match result {
+            Ok(presses) => total = checked_add(total, presses.cost)?,
+            Err(Rejection::Overflow) => return Err(Rejection::Overflow),
+            Err(_) => {}
+        }
+    }
+    Ok(total)
+}
+
+// each rejected machine is only listed with `verbose`
+fn report(results: &[(usize, Result<ButtonPresses, Rejection>)], verbose: bool) {
+    let rejected: Vec<_> = results
+        .iter()
+        .filter_map(|(i, result)| result.as_ref().err().map(|rejection| (i, rejection)))
+        .collect();
+    if verbose {
+        for (i, rejection) in &rejected {
+            println!("Machine {} rejected: {:?}", i, rejection);
+        }
+    }
+    println!("{} of {} machines rejected", rejected.len(), results.len());
+
+    match total_cost(results) {
+        Ok(total) => println!("Total cost: {}", total),
+        Err(err) => println!("Failed to compute total cost: {:?}", err),
+    }
+}
+
+fn part01(verbose: bool) {
+    let input = get_input("input.txt");
+    let machines = match parse_input(&input, false, TOKEN_COSTS) {
+        Ok(machines) => machines,
+        Err(err) => {
+            println!("Failed to parse input: {}", err);
+            return;
+        }
+    };
+
+    let options = SolverOptions {
+        non_negative: true,
+        max_presses: Some(100),
+    };
+    report(&solve_machines(&machines, options), verbose);
+}
+
+fn part02(verbose: bool) {
+    let input = get_input("input.txt");
+    let machines = match parse_input(&input, true, TOKEN_COSTS) {
+        Ok(machines) => machines,
+        Err(err) => {
+            println!("Failed to parse input: {}", err);
+            return;
+        }
+    };
+
+    let options = SolverOptions {
+        non_negative: true,
+        max_presses: None,
+    };
+    report(&solve_machines(&machines, options), verbose);
 }
 
 fn main() {
-    part01();
-    part02();
+    let verbose = std::env::args().any(|arg| arg == "--verbose");
+    part01(verbose);
+    part02(verbose);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: SolverOptions = SolverOptions {
+        non_negative: true,
+        max_presses: Some(100),
+    };
+
+    fn minimal_cost(machine: &ClawMachine, options: SolverOptions) -> Result<i128, Rejection> {
+        solve_machine(machine, options).map(|presses| presses.cost)
+    }
+
+    fn costs(input: &str) -> Vec<i128> {
+        parse_input(input, false, TOKEN_COSTS)
+            .unwrap()
+            .iter()
+            .map(|machine| minimal_cost(machine, OPTIONS).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn costs_follow_labels_not_order() {
+        let ordered = "Button A: X+94, Y+34\n\
+                       Button B: X+22, Y+67\n\
+                       Prize: X=8400, Y=5400\n";
+        let reordered = "Prize: X=8400, Y=5400\r\n\
+                         Button B: X+22, Y+67\r\n\
+                         Button A: X+94, Y+34\r\n";
+        assert_eq!(costs(ordered), vec![280]);
+        assert_eq!(costs(reordered), vec![280]);
+    }
+
+    #[test]
+    fn unknown_label_has_no_cost() {
+        let input = "Button C: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400, Y=5400\n";
+        let err = parse_input(input, false, TOKEN_COSTS).unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+
+    fn machine(deltas: &[(i128, i128)], costs: &[i128], prize: (i128, i128)) -> ClawMachine {
+        ClawMachine {
+            buttons: deltas
+                .iter()
+                .zip(costs)
+                .map(|(&(x, y), &cost)| Button {
+                    delta: IVec2::new(x, y),
+                    cost,
+                })
+                .collect(),
+            prize_pos: IVec2::new(prize.0, prize.1),
+        }
+    }
+
+    // tries every combination of up to `limit` presses of each button
+    fn brute_force(machine: &ClawMachine, limit: i128) -> Option<i128> {
+        fn search(buttons: &[Button], remaining: IVec2, limit: i128) -> Option<i128> {
+            let Some((button, rest)) = buttons.split_first() else {
+                return (remaining == IVec2::new(0, 0)).then_some(0);
+            };
+            (0..=limit)
+                .filter_map(|count| {
+                    let moved = IVec2::new(button.delta.x * count, button.delta.y * count);
+                    let remaining = IVec2::new(remaining.x - moved.x, remaining.y - moved.y);
+                    search(rest, remaining, limit).map(|cost| cost + count * button.cost)
+                })
+                .min()
+        }
+        search(&machine.buttons, machine.prize_pos, limit)
+    }
+
+    // a small deterministic pseudo-random generator, so the cases are reproducible
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: i128) -> i128 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % bound as u64) as i128
+        }
+    }
+
+    // machines whose prize is usually reachable with at most `limit` presses
+    fn random_machines(buttons: usize, count: usize, limit: i128) -> Vec<ClawMachine> {
+        let mut rng = Lcg(buttons as u64);
+        (0..count)
+            .map(|_| {
+                let deltas: Vec<(i128, i128)> =
+                    (0..buttons).map(|_| (rng.next(13), rng.next(13))).collect();
+                let costs: Vec<i128> = (0..buttons).map(|_| 1 + rng.next(5)).collect();
+                let mut prize = (rng.next(3), rng.next(3));
+                for &(x, y) in &deltas {
+                    let presses = rng.next(limit + 1);
+                    prize = (prize.0 + presses * x, prize.1 + presses * y);
+                }
+                machine(&deltas, &costs, prize)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn three_buttons_match_brute_force() {
+        let machines = [
+            machine(&[(3, 1), (1, 2), (2, 2)], &[3, 1, 2], (40, 30)),
+            machine(&[(4, 0), (0, 3), (1, 1)], &[1, 1, 5], (27, 21)),
+            machine(&[(2, 4), (1, 2), (3, 6)], &[2, 1, 2], (14, 28)),
+            machine(&[(6, 4), (9, 6), (0, 0)], &[1, 1, 1], (33, 22)),
+            machine(&[(5, 3), (7, 2), (2, 9)], &[3, 2, 4], (1, 1)),
+        ];
+        for machine in &machines {
+            let options = SolverOptions {
+                non_negative: true,
+                max_presses: Some(20),
+            };
+            assert_eq!(
+                minimal_cost(machine, options).ok(),
+                brute_force(machine, 20),
+                "{:?}",
+                machine
+            );
+        }
+    }
+
+    #[test]
+    fn three_buttons_with_error_correction() {
+        let offset = ERROR_CORRECTION;
+        let machine = machine(
+            &[(94, 34), (22, 67), (57, 51)],
+            &[3, 1, 2],
+            (8400 + offset, 5400 + offset),
+        );
+        let options = SolverOptions {
+            non_negative: true,
+            max_presses: None,
+        };
+        let presses = solve_machine(&machine, options).unwrap();
+        assert!(presses.presses.iter().all(|&count| count >= 0));
+        let mut pos = IVec2::new(0, 0);
+        for (&count, button) in presses.presses.iter().zip(&machine.buttons) {
+            pos = pos
+                .checked_add(button.delta.checked_mul(count).unwrap())
+                .unwrap();
+        }
+        assert_eq!(pos, machine.prize_pos);
+    }
+
+    #[test]
+    fn negative_presses_are_not_silently_forbidden() {
+        let machine = machine(&[(3, 1), (1, 2), (2, 2)], &[3, 1, 2], (40, 30));
+        let options = SolverOptions {
+            non_negative: false,
+            max_presses: None,
+        };
+        assert_eq!(minimal_cost(&machine, options), Err(Rejection::Unbounded));
+    }
+
+    #[test]
+    fn more_buttons_match_brute_force() {
+        for (buttons, count, limit) in [(4, 200, 12), (5, 40, 6)] {
+            for machine in random_machines(buttons, count, limit) {
+                let options = SolverOptions {
+                    non_negative: true,
+                    max_presses: Some(limit),
+                };
+                assert_eq!(
+                    minimal_cost(&machine, options).ok(),
+                    brute_force(&machine, limit),
+                    "{:?}",
+                    machine
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn four_buttons_with_error_correction() {
+        // every button costs at least 4/3 tokens per step of x + y, which only
+        // C and D reach, so a solution with a + b presses of A and B costs
+        // 4/3 (x + y) + 5/3 (a + b). a + b must be congruent to x + y modulo 3,
+        // so with x + y = 2 mod 3 the cheapest solution presses A or B once
+        let target = ERROR_CORRECTION + 1;
+        let proven = machine(
+            &[(1, 0), (0, 1), (2, 1), (1, 2)],
+            &[3, 3, 4, 4],
+            (target, target),
+        );
+        let options = SolverOptions {
+            non_negative: true,
+            max_presses: None,
+        };
+        assert_eq!(minimal_cost(&proven, options), Ok((4 * 2 * target + 5) / 3));
+        assert_eq!((4 * 2 * target + 5) % 3, 0);
+
+        // a four button machine at the part 2 scale lands exactly on the prize
+        let offset = ERROR_CORRECTION;
+        let machine = machine(
+            &[(94, 34), (22, 67), (57, 51), (13, 88)],
+            &[3, 1, 2, 1],
+            (8400 + offset, 5400 + offset),
+        );
+        let presses = solve_machine(&machine, options).unwrap();
+        let mut pos = IVec2::new(0, 0);
+        for (&count, button) in presses.presses.iter().zip(&machine.buttons) {
+            assert!(count >= 0);
+            pos = pos
+                .checked_add(button.delta.checked_mul(count).unwrap())
+                .unwrap();
+        }
+        assert_eq!(pos, machine.prize_pos);
+    }
+
+    #[test]
+    fn buttons_moving_backwards() {
+        let options = SolverOptions {
+            non_negative: true,
+            max_presses: None,
+        };
+        // every button costs at least one token, so no solution cheaper than
+        // the one found can press any button more than `limit` times
+        let limit = 40;
+        let machines = [
+            machine(&[(5, 1), (-2, 3), (1, 4)], &[2, 1, 3], (15, 25)),
+            machine(&[(5, 1), (-2, 3), (1, -1), (3, 4)], &[2, 1, 1, 3], (18, 16)),
+            machine(&[(7, -3), (-1, 5), (2, 2), (0, 1)], &[1, 2, 4, 3], (13, 13)),
+        ];
+        for machine in &machines {
+            let cost = minimal_cost(machine, options).unwrap();
+            assert!(cost <= limit);
+            assert_eq!(Some(cost), brute_force(machine, limit), "{:?}", machine);
+        }
+
+        // five buttons leave a third free parameter, which needs press bounds
+        let machine = machine(
+            &[(5, 1), (-2, 3), (1, -1), (3, 4), (2, 7)],
+            &[2, 1, 1, 3, 2],
+            (18, 16),
+        );
+        assert_eq!(
+            minimal_cost(&machine, options),
+            Err(Rejection::NoPressBound)
+        );
+    }
 }
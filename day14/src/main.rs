@@ -1,4 +1,7 @@
-use std::{collections::HashSet, ops::Add};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    ops::Add,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct IVec2 {
@@ -87,24 +90,136 @@ fn tick_robots(robots: &mut [Robot], map_size: IVec2) {
 
 const MAP_SIZE: IVec2 = IVec2 { x: 101, y: 103 };
 
-fn part01() {
-    let input = get_input("input.txt");
-    let mut robots = parse_input(&input);
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
 
-    for _ in 0..100 {
-        tick_robots(&mut robots, MAP_SIZE);
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
+
+// number of ticks before a single coordinate returns to its start
+fn axis_period(velocity: i64, size: i64) -> i64 {
+    size / gcd(velocity.rem_euclid(size), size)
+}
+
+fn orbit_period(robot: &Robot, map_size: IVec2) -> i64 {
+    lcm(
+        axis_period(robot.velocity.x, map_size.x),
+        axis_period(robot.velocity.y, map_size.y),
+    )
+}
+
+// number of ticks after which every robot is back at its start
+fn system_period(robots: &[Robot], map_size: IVec2) -> i64 {
+    robots
+        .iter()
+        .map(|robot| orbit_period(robot, map_size))
+        .fold(1, lcm)
+}
+
+fn robot_counts(robots: &[Robot]) -> HashMap<IVec2, usize> {
+    let mut counts = HashMap::new();
+    for robot in robots {
+        *counts.entry(robot.position).or_insert(0) += 1;
     }
+    counts
+}
 
-    // if any robot has a negative position or is outside the map, print it
-    for robot in &robots {
-        if robot.position.x < 0 || robot.position.y < 0 {
-            println!("Negative position: {:?}", robot);
+// number of cells occupied by more than one robot
+fn count_collisions(robots: &[Robot]) -> usize {
+    robot_counts(robots)
+        .values()
+        .filter(|&&count| count > 1)
+        .count()
+}
+
+// maps cluster size (in robots) to the number of clusters of that size,
+// where occupied cells are connected if they are orthogonal neighbours on the torus
+fn cluster_histogram(robots: &[Robot], map_size: IVec2) -> BTreeMap<usize, usize> {
+    let counts = robot_counts(robots);
+    let mut visited = HashSet::new();
+    let mut histogram = BTreeMap::new();
+
+    for &start in counts.keys() {
+        if !visited.insert(start) {
+            continue;
+        }
+
+        let mut size = 0;
+        let mut queue = VecDeque::from([start]);
+        while let Some(pos) = queue.pop_front() {
+            size += counts[&pos];
+            for dir in [
+                IVec2::new(1, 0),
+                IVec2::new(-1, 0),
+                IVec2::new(0, 1),
+                IVec2::new(0, -1),
+            ] {
+                let next = (pos + dir).wrap(map_size);
+                if counts.contains_key(&next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
         }
-        if robot.position.x >= MAP_SIZE.x || robot.position.y >= MAP_SIZE.y {
-            println!("Outside map: {:?}", robot);
+
+        *histogram.entry(size).or_insert(0) += 1;
+    }
+
+    histogram
+}
+
+#[derive(Debug)]
+struct TickStats {
+    tick: usize,
+    collisions: usize,
+    clusters: BTreeMap<usize, usize>,
+}
+
+impl TickStats {
+    fn new(tick: usize, robots: &[Robot], map_size: IVec2) -> Self {
+        Self {
+            tick,
+            collisions: count_collisions(robots),
+            clusters: cluster_histogram(robots, map_size),
         }
     }
 
+    fn largest_cluster(&self) -> usize {
+        self.clusters.keys().last().copied().unwrap_or(0)
+    }
+}
+
+fn simulate_with_stats(robots: &mut [Robot], map_size: IVec2, ticks: usize) -> Vec<TickStats> {
+    let mut stats = Vec::with_capacity(ticks);
+    for tick in 1..=ticks {
+        tick_robots(robots, map_size);
+        stats.push(TickStats::new(tick, robots, map_size));
+    }
+    stats
+}
+
+fn part01() {
+    let input = get_input("input.txt");
+    let mut robots = parse_input(&input);
+
+    println!("System period: {}", system_period(&robots, MAP_SIZE));
+
+    let stats = simulate_with_stats(&mut robots, MAP_SIZE, 100);
+    if let Some(last) = stats.last() {
+        println!(
+            "After {} ticks: {} collisions, largest cluster {}, clusters {:?}",
+            last.tick,
+            last.collisions,
+            last.largest_cluster(),
+            last.clusters
+        );
+    }
+
     // count the number of robots in each quadrant
     let mut count = [0; 4];
     for robot in &robots {